@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// Any input must yield Ok or Err, never a panic
+fuzz_target!(|data: &[u8]| {
+	let _ = rexif::parse_buffer(data);
+	let _ = rexif::parse_buffer_pages(data);
+});
@@ -0,0 +1,129 @@
+use super::lowlevel::*;
+use super::source::ByteSource;
+
+/// Box of the ISO base media file format (ISO/IEC 14496-12), the container
+/// of HEIF, AVIF, JPEG XL and CR3 images
+#[derive(Clone, Debug)]
+pub struct BmffBox {
+	/// Four-character code of the box type
+	pub box_type: [u8; 4],
+	/// Extended type of "uuid" boxes, zeros for other boxes
+	pub uuid: [u8; 16],
+	/// Offset of the box contents, after the header
+	pub offset: usize,
+	/// Size of the box contents
+	pub size: usize,
+}
+
+/// Lists the boxes found between `offset` and `end`, e.g. the children of
+/// another box. The listing stops at the first broken box.
+pub fn read_boxes<S: ByteSource>(contents: &mut S, offset: usize, end: usize) -> Vec<BmffBox>
+{
+	let mut boxes: Vec<BmffBox> = Vec::new();
+	let mut offset = offset;
+	let end = end.min(contents.size());
+
+	while offset < end {
+		let header = match contents.read_at(offset, 8) {
+			Some(header) => header,
+			None => break,
+		};
+
+		let mut box_type = [0u8; 4];
+		box_type.copy_from_slice(&header[4..8]);
+		let mut header_size: usize = 8;
+
+		let size = match read_u32(false, &header[0..4]) {
+			// box goes up to the end of its parent
+			0 => end - offset,
+			// 64-bit size follows the box type
+			1 => match contents.read_at(offset + 8, 8) {
+				Some(size) => {
+					header_size = 16;
+					read_u64(false, &size) as usize
+				},
+				None => break,
+			},
+			size => size as usize,
+		};
+
+		let mut uuid = [0u8; 16];
+		if box_type == *b"uuid" {
+			match contents.read_at(offset + header_size, 16) {
+				Some(raw) => uuid.copy_from_slice(&raw),
+				None => break,
+			}
+			header_size += 16;
+		}
+
+		if size < header_size || size > end - offset {
+			break;
+		}
+
+		boxes.push(BmffBox {
+			box_type,
+			uuid,
+			offset: offset + header_size,
+			size: size - header_size,
+		});
+
+		offset += size;
+	}
+
+	return boxes;
+}
+
+/// Lists the boxes of a buffer, starting at `offset`, e.g. the children of
+/// a box that was read into memory
+pub fn read_boxes_in(data: &[u8], offset: usize) -> Vec<BmffBox>
+{
+	let mut source = data;
+	return read_boxes(&mut source, offset, data.len());
+}
+
+/// Finds the first box of a given type
+pub fn find_box<'a>(boxes: &'a [BmffBox], box_type: &[u8]) -> Option<&'a BmffBox>
+{
+	boxes.iter().find(|b| b.box_type[..] == *box_type)
+}
+
+/// Reads the big-endian fields of a box, one after the other
+pub struct FieldReader<'a> {
+	data: &'a [u8],
+	pub pos: usize,
+}
+
+impl<'a> FieldReader<'a> {
+	pub fn new(data: &'a [u8], pos: usize) -> FieldReader<'a>
+	{
+		FieldReader { data, pos }
+	}
+
+	/// Reads an unsigned integer of 0 to 8 bytes. Zero-sized fields are
+	/// found e.g. in "iloc" boxes, and read as 0.
+	pub fn read(&mut self, size: usize) -> Option<u64>
+	{
+		if size > 8 {
+			return None;
+		}
+		let raw = slice_at(self.data, self.pos, size)?;
+		self.pos += size;
+		return Some(raw.iter().fold(0u64, |n, &byte| (n << 8) | u64::from(byte)));
+	}
+
+	/// Reads a field of either 2 or 4 bytes, depending on a box version
+	pub fn read_short_or_long(&mut self, long: bool) -> Option<u64>
+	{
+		self.read(if long {4} else {2})
+	}
+
+	/// Reads a four-character code
+	pub fn read_fourcc(&mut self) -> Option<[u8; 4]>
+	{
+		let raw = slice_at(self.data, self.pos, 4)?;
+		self.pos += 4;
+		let mut fourcc = [0u8; 4];
+		fourcc.copy_from_slice(raw);
+		return Some(fourcc);
+	}
+}
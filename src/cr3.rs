@@ -0,0 +1,203 @@
+use super::types::*;
+use super::source::*;
+use super::bmff::*;
+use super::tiff::parse_tiff_block;
+use super::xmp::{xmp_from_entries, MAX_XMP_SIZE};
+use super::iptc::iptc_from_entries;
+
+/// Extended type of the "uuid" box, within "moov", that holds Canon metadata
+static CANON_UUID: [u8; 16] = [0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0,
+				0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48];
+
+/// Extended type of the top-level "uuid" box that holds the XMP packet
+static XMP_UUID: [u8; 16] = [0xbe, 0x7a, 0xcf, 0xcb, 0x97, 0xa9, 0x42, 0xe8,
+				0x9c, 0x71, 0x99, 0x94, 0x91, 0xe3, 0xaf, 0xac];
+
+/// Boxes of CR3 metadata, each one a standalone TIFF block with a single IFD,
+/// and the IFD each one stands for
+static CR3_BLOCKS: [(&[u8; 4], IfdKind); 4] = [
+	(b"CMT1", IfdKind::Ifd0),
+	(b"CMT2", IfdKind::Exif),
+	(b"CMT3", IfdKind::MakerNote),
+	(b"CMT4", IfdKind::Gps),
+];
+
+/// Lists the boxes within the Canon "uuid" box
+fn canon_boxes<S: ByteSource>(contents: &mut S) -> Result<Vec<BmffBox>, ExifError>
+{
+	let size = contents.size();
+	let top = read_boxes(contents, 0, size);
+	let moov = match find_box(&top, b"moov") {
+		Some(moov) => moov.clone(),
+		None => return Err(ExifError::Cr3WithoutExif("No moov box".to_string())),
+	};
+
+	let canon = read_boxes(contents, moov.offset, moov.offset + moov.size).into_iter()
+		.find(|b| b.box_type == *b"uuid" && b.uuid == CANON_UUID);
+	let canon = match canon {
+		Some(canon) => canon,
+		None => return Err(ExifError::Cr3WithoutExif("No Canon uuid box".to_string())),
+	};
+
+	return Ok(read_boxes(contents, canon.offset, canon.offset + canon.size));
+}
+
+/// Find the main TIFF block (CMT1 box) of a Canon CR3 image.
+/// Returns offset and size of the TIFF block.
+pub fn find_embedded_tiff_in_cr3<S: ByteSource>(contents: &mut S)
+					-> Result<(usize, usize), ExifError>
+{
+	let boxes = canon_boxes(contents)?;
+	match find_box(&boxes, b"CMT1") {
+		Some(cmt1) => Ok((cmt1.offset, cmt1.size)),
+		None => Err(ExifError::Cr3WithoutExif("No CMT1 box".to_string())),
+	}
+}
+
+/// Find the XMP packet of a Canon CR3 image, stored in a top-level "uuid" box.
+/// Returns None if there is no such box.
+pub fn find_xmp_in_cr3<S: ByteSource>(contents: &mut S) -> Option<Vec<u8>>
+{
+	let size = contents.size();
+	let xmp = read_boxes(contents, 0, size).into_iter()
+		.find(|b| b.box_type == *b"uuid" && b.uuid == XMP_UUID)?;
+	if xmp.size > MAX_XMP_SIZE {
+		return None;
+	}
+	return contents.read_at(xmp.offset, xmp.size);
+}
+
+/// Parse the metadata of a Canon CR3 image. It is split in four TIFF blocks:
+/// CMT1 (IFD0), CMT2 (Exif IFD), CMT3 (Canon MakerNote) and CMT4 (GPS IFD),
+/// that are merged in a single `ExifData`. The block each entry came from is
+/// told by `ExifEntry::ifd_kind`. Offsets of warnings are relative to the block.
+///
+/// Only CMT1 is mandatory. Other blocks may be missing, and a broken one is
+/// reported as a warning.
+pub fn parse_cr3<S: ByteSource>(contents: &mut S, options: &ParseOptions) -> ExifResult
+{
+	let boxes = canon_boxes(contents)?;
+
+	let mut entries: Vec<ExifEntry> = Vec::new();
+	let mut warnings: Vec<ExifWarning> = Vec::new();
+
+	for &(box_type, kind) in CR3_BLOCKS.iter() {
+		let block = match find_box(&boxes, box_type) {
+			Some(block) => block,
+			None => {
+				if kind == IfdKind::Ifd0 {
+					return Err(ExifError::Cr3WithoutExif("No CMT1 box".to_string()));
+				}
+				continue;
+			}
+		};
+
+		let mut source = SubSource::new(contents, block.offset, block.size);
+		let mut d = match parse_tiff_block(&mut source, kind, options) {
+			Ok(d) => d,
+			Err(e) => {
+				if kind == IfdKind::Ifd0 {
+					return Err(e);
+				}
+				warnings.push(ExifWarning {
+					tag: 0,
+					ifd_kind: kind,
+					offset: 0,
+					kind: ExifWarningKind::IfdTruncated(e.to_string()),
+				});
+				continue;
+			}
+		};
+
+		if kind == IfdKind::MakerNote {
+			for entry in &mut d.entries {
+				entry.namespace = Namespace::Canon;
+				entry.ifd.namespace = Namespace::Canon;
+			}
+		}
+
+		entries.append(&mut d.entries);
+		warnings.append(&mut d.warnings);
+	}
+
+	let xmp = xmp_from_entries(&entries);
+	let iptc = iptc_from_entries(&entries);
+
+	return Ok(ExifData {
+		mime: "".to_string(),
+		entries,
+		thumbnail: None,
+		warnings,
+		xmp,
+		iptc,
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testutil::*;
+
+	/// TIFF block with a single IFD
+	fn block(entries: &[Entry]) -> Vec<u8>
+	{
+		let mut tiff = Tiff::new(true);
+		let ifd = tiff.ifd(entries, 0);
+		return tiff.finish(ifd);
+	}
+
+	/// CR3 image with the given boxes in the Canon "uuid" box
+	fn cr3(boxes: &[(&[u8], Vec<u8>)]) -> Vec<u8>
+	{
+		let canon: Vec<u8> = boxes.iter().flat_map(|&(box_type, ref data)| bmff_box(box_type, data)).collect();
+		let moov = [bmff_box(b"mvhd", &[0; 100]), bmff_box(b"uuid", &[&CANON_UUID[..], &canon].concat())].concat();
+		return [bmff_box(b"ftyp", b"crx \0\0\0\x01crx isom"), bmff_box(b"moov", &moov), bmff_box(b"mdat", b"image")].concat();
+	}
+
+	fn cmt1() -> Vec<u8>
+	{
+		return block(&[ascii(0x010f, "Canon"), ascii(0x0110, "Canon EOS R5")]);
+	}
+
+	#[test]
+	fn merged_blocks() {
+		let image = cr3(&[
+			(b"CMT1", cmt1()),
+			(b"CMT2", block(&[entry(0x829a, 5, &[1, 100])])),
+			(b"CMT3", block(&[entry(0x0001, 3, &[1, 2, 3])])),
+			(b"CMT4", block(&[ascii(0x0001, "S")])),
+		]);
+		let d = ::parse_buffer(&image).unwrap();
+		assert_eq!(d.mime, "image/x-canon-cr3");
+		assert!(d.warnings.is_empty());
+
+		let entries: Vec<(IfdKind, Namespace, ExifTag)> = d.entries.iter()
+			.map(|e| (e.ifd_kind, e.namespace, e.tag)).collect();
+		assert_eq!(entries, vec![
+			(IfdKind::Ifd0, Namespace::Standard, ExifTag::Make),
+			(IfdKind::Ifd0, Namespace::Standard, ExifTag::Model),
+			(IfdKind::Exif, Namespace::Standard, ExifTag::ExposureTime),
+			(IfdKind::MakerNote, Namespace::Canon, ExifTag::UnknownToMe),
+			(IfdKind::Gps, Namespace::Standard, ExifTag::GPSLatitudeRef),
+		]);
+		assert_eq!(d.entries[2].value_more_readable, "1/100 s");
+		assert_eq!(d.entries[3].ifd.namespace, Namespace::Canon);
+
+		let (offset, size) = find_embedded_tiff_in_cr3(&mut &image[..]).unwrap();
+		assert_eq!(&image[offset..offset + size], &cmt1()[..]);
+	}
+
+	#[test]
+	fn broken_blocks() {
+		// only CMT1 is mandatory
+		let image = cr3(&[(b"CMT1", cmt1()), (b"CMT2", b"II\x2a\0\xff\0\0\0".to_vec())]);
+		let d = parse_cr3(&mut &image[..], &ParseOptions::default()).unwrap();
+		assert_eq!(d.entries.len(), 2);
+		assert_eq!(d.warnings.len(), 1);
+		assert_eq!(d.warnings[0].ifd_kind, IfdKind::Exif);
+
+		let image = cr3(&[(b"CMT2", block(&[entry(0x829a, 5, &[1, 100])]))]);
+		assert!(parse_cr3(&mut &image[..], &ParseOptions::default()).is_err());
+		assert!(find_embedded_tiff_in_cr3(&mut &image[..]).is_err());
+	}
+}
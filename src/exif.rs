@@ -1,14 +1,79 @@
 use super::types::*;
 use super::exifreadable::*;
 
-/// Convert a numeric tag into ExifTag enumeration, and yields information about the tag. This information
+/// Information about a known tag: enumeration, unit, expected format, minimum and
+/// maximum count (-1 if not fixed), and function that makes the value readable
+pub type TagInfo = (ExifTag, &'static str, IfdFormat, i32, i32, fn(&TagValue) -> String);
+
+/// Convert a numeric tag, found in a given IFD, into ExifTag enumeration, and yields
+/// information about the tag. This information
 /// is used by the main body of the parser to sanity-check the tags found in image
 /// and make sure that EXIF tags have the right data types
-pub fn tag_to_exif(f: u16) -> (ExifTag, &'static str, IfdFormat, i32, i32,
-					           fn(&TagValue) -> String)
+pub fn tag_to_exif(kind: IfdKind, f: u16) -> TagInfo
+{
+	match kind {
+		IfdKind::Ifd0 | IfdKind::Ifd1 | IfdKind::Page(_) | IfdKind::SubIfd(_) |
+			IfdKind::Exif => tiff_tag_to_exif(f),
+		IfdKind::Gps => gps_tag_to_exif(f),
+		IfdKind::Interop => interop_tag_to_exif(f),
+		IfdKind::MakerNote => unknown_tag(),
+	}
+}
+
+/// Checks the information about a tag found in the tables:
+/// 1) tag must match enum
+/// 2) all types except Ascii, Undefined, Unknown must have definite length
+/// 3) Str type must not have a definite length
+pub fn tag_info_is_consistent(f: u16, info: &TagInfo) -> bool
+{
+	let (tag, _, format, min_count, _, _) = *info;
+
+	if tag == ExifTag::UnknownToMe {
+		return true;
+	}
+
+	return (((tag as u32) & 0xffff) as u16) == f &&
+		(min_count != -1 || format == IfdFormat::Ascii ||
+			format == IfdFormat::Undefined ||
+			format == IfdFormat::Unknown) &&
+		(min_count == -1 || format != IfdFormat::Ascii);
+}
+
+/// Tag information for tags that are not known, or manufacturer-specific
+fn unknown_tag() -> TagInfo
+{
+	(ExifTag::UnknownToMe, "Unknown unit",
+	IfdFormat::Unknown, -1i32, -1i32, nop)
+}
+
+/// Tags found in the TIFF IFD chain (IFD0, IFD1...) and in the Exif IFD. Many
+/// writers mix TIFF and Exif tags, so they share the same table.
+fn tiff_tag_to_exif(f: u16) -> TagInfo
 {
 	match f {
 
+	0x00fe =>
+	(ExifTag::NewSubfileType, "none", IfdFormat::U32, 1, 1, new_subfile_type),
+
+	0x0100 =>
+	(ExifTag::ImageWidth, "pixels", IfdFormat::U32, 1, 1, strpass),
+
+	0x0101 =>
+	(ExifTag::ImageLength, "pixels", IfdFormat::U32, 1, 1, strpass),
+
+	0x0102 =>
+	(ExifTag::BitsPerSample, "bits", IfdFormat::U16, 1, i32::MAX, strpass),
+
+	0x0103 =>
+	(ExifTag::Compression, "none", IfdFormat::U16, 1, 1, compression),
+
+	0x0106 =>
+	(ExifTag::PhotometricInterpretation, "none",
+	IfdFormat::U16, 1, 1, photometric_interpretation),
+
+	0x010d =>
+	(ExifTag::DocumentName, "none", IfdFormat::Ascii, -1i32, -1i32, strpass),
+
 	0x010e =>
 	(ExifTag::ImageDescription, "none", IfdFormat::Ascii,
 	-1i32, -1i32, strpass),
@@ -22,9 +87,23 @@ pub fn tag_to_exif(f: u16) -> (ExifTag, &'static str, IfdFormat, i32, i32,
 	0x0110 =>
 	(ExifTag::Model, "none", IfdFormat::Ascii, -1i32, -1i32, strpass),
 
+	0x0111 =>
+	(ExifTag::StripOffsets, "byte offset",
+	IfdFormat::U32, 1, i32::MAX, value_list),
+
 	0x0112 =>
 	(ExifTag::Orientation, "none", IfdFormat::U16, 1, 1, orientation),
 
+	0x0115 =>
+	(ExifTag::SamplesPerPixel, "none", IfdFormat::U16, 1, 1, strpass),
+
+	0x0116 =>
+	(ExifTag::RowsPerStrip, "rows", IfdFormat::U32, 1, 1, strpass),
+
+	0x0117 =>
+	(ExifTag::StripByteCounts, "bytes",
+	IfdFormat::U32, 1, i32::MAX, value_list),
+
 	0x011a =>
 	(ExifTag::XResolution, "pixels per res unit",
 	IfdFormat::URational, 1, 1, rational_value),
@@ -33,9 +112,19 @@ pub fn tag_to_exif(f: u16) -> (ExifTag, &'static str, IfdFormat, i32, i32,
 	(ExifTag::YResolution, "pixels per res unit",
 	IfdFormat::URational, 1, 1, rational_value),
 
+	0x011c =>
+	(ExifTag::PlanarConfiguration, "none",
+	IfdFormat::U16, 1, 1, planar_configuration),
+
+	0x011d =>
+	(ExifTag::PageName, "none", IfdFormat::Ascii, -1i32, -1i32, strpass),
+
 	0x0128 =>
 	(ExifTag::ResolutionUnit, "none", IfdFormat::U16, 1, 1, resolution_unit),
 
+	0x0129 =>
+	(ExifTag::PageNumber, "none", IfdFormat::U16, 2, 2, page_number),
+
 	0x0131 =>
 	(ExifTag::Software, "none", IfdFormat::Ascii, -1i32, -1i32, strpass),
 
@@ -50,10 +139,22 @@ pub fn tag_to_exif(f: u16) -> (ExifTag, &'static str, IfdFormat, i32, i32,
 	(ExifTag::PrimaryChromaticities, "CIE 1931 coordinates",
 	IfdFormat::URational, 6, 6, rational_values),
 
+	0x014a =>
+	(ExifTag::SubIFDs, "byte offset",
+	IfdFormat::U32, 1, i32::MAX, value_list),
+
 	0x0211 =>
 	(ExifTag::YCbCrCoefficients, "none",
 	IfdFormat::URational, 3, 3, rational_values),
 
+	0x0201 =>
+	(ExifTag::JPEGInterchangeFormat, "byte offset",
+	IfdFormat::U32, 1, 1, strpass),
+
+	0x0202 =>
+	(ExifTag::JPEGInterchangeFormatLength, "bytes",
+	IfdFormat::U32, 1, 1, strpass),
+
 	0x0214 =>
 	(ExifTag::ReferenceBlackWhite, "RGB or YCbCr",
 	IfdFormat::URational, 6, 6, rational_values),
@@ -172,6 +273,10 @@ pub fn tag_to_exif(f: u16) -> (ExifTag, &'static str, IfdFormat, i32, i32,
 	(ExifTag::RelatedSoundFile, "none",
 	IfdFormat::Ascii, -1i32, -1i32, strpass),
 
+	0xa005 =>
+	(ExifTag::InteropOffset, "byte offset",
+	IfdFormat::U32, 1, 1, strpass),
+
 	0xa20b => (ExifTag::FlashEnergy, "BCPS",
 	IfdFormat::URational, 1, 1, flash_energy),
 
@@ -280,7 +385,17 @@ pub fn tag_to_exif(f: u16) -> (ExifTag, &'static str, IfdFormat, i32, i32,
 	0xa420 =>
 	(ExifTag::ImageUniqueID, "none",
 	IfdFormat::Ascii, -1i32, -1i32, strpass),
-		
+
+	_ => unknown_tag()
+
+	}
+}
+
+/// Tags found in the GPS IFD
+fn gps_tag_to_exif(f: u16) -> TagInfo
+{
+	match f {
+
 	0x0 =>
 	(ExifTag::GPSVersionID, "none",
 	IfdFormat::U8, 4, 4, strpass),
@@ -401,9 +516,69 @@ pub fn tag_to_exif(f: u16) -> (ExifTag, &'static str, IfdFormat, i32, i32,
 	(ExifTag::GPSDifferential, "none",
 	IfdFormat::U16, 1, 1, gpsdiff),
 
-	_ =>
-	(ExifTag::UnknownToMe, "Unknown unit",
-	IfdFormat::Unknown, -1i32, -1i32, nop)
+	_ => unknown_tag()
+
+	}
+}
+
+/// Tags found in the Interoperability IFD
+fn interop_tag_to_exif(f: u16) -> TagInfo
+{
+	match f {
+
+	0x1 =>
+	(ExifTag::InteroperabilityIndex, "none",
+	IfdFormat::Ascii, -1i32, -1i32, interop_index),
+
+	0x2 =>
+	(ExifTag::InteroperabilityVersion, "none",
+	IfdFormat::Undefined, -1i32, -1i32, undefined_as_ascii),
+
+	0x1000 =>
+	(ExifTag::RelatedImageFileFormat, "none",
+	IfdFormat::Ascii, -1i32, -1i32, strpass),
+
+	0x1001 =>
+	(ExifTag::RelatedImageWidth, "pixels",
+	IfdFormat::U16, 1, 1, strpass),
+
+	0x1002 =>
+	(ExifTag::RelatedImageLength, "pixels",
+	IfdFormat::U16, 1, 1, strpass),
+
+	_ => unknown_tag()
+
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tags_per_ifd() {
+		// tag 0x0001 means something different in each IFD
+		assert_eq!(tag_to_exif(IfdKind::Ifd0, 0x0001).0, ExifTag::UnknownToMe);
+		assert_eq!(tag_to_exif(IfdKind::Exif, 0x0001).0, ExifTag::UnknownToMe);
+		assert_eq!(tag_to_exif(IfdKind::Gps, 0x0001).0, ExifTag::GPSLatitudeRef);
+		assert_eq!(tag_to_exif(IfdKind::Interop, 0x0001).0, ExifTag::InteroperabilityIndex);
+		assert!(ExifTag::GPSLatitudeRef != ExifTag::InteroperabilityIndex);
+
+		// TIFF and Exif tags are only known in their own IFDs
+		assert_eq!(tag_to_exif(IfdKind::Ifd0, 0x010f).0, ExifTag::Make);
+		assert_eq!(tag_to_exif(IfdKind::Gps, 0x010f).0, ExifTag::UnknownToMe);
+		assert_eq!(tag_to_exif(IfdKind::Interop, 0x010f).0, ExifTag::UnknownToMe);
+		assert_eq!(tag_to_exif(IfdKind::MakerNote, 0x0001).0, ExifTag::UnknownToMe);
+	}
 
+	#[test]
+	fn tables_are_consistent() {
+		let kinds = [IfdKind::Ifd0, IfdKind::Ifd1, IfdKind::Page(2), IfdKind::SubIfd(0),
+			IfdKind::Exif, IfdKind::Gps, IfdKind::Interop, IfdKind::MakerNote];
+		for &kind in &kinds {
+			for f in 0..=0xffff {
+				assert!(tag_info_is_consistent(f, &tag_to_exif(kind, f)), "{:?} {:x}", kind, f);
+			}
+		}
 	}
 }
@@ -1,24 +1,21 @@
 use super::types::*;
 
-/// Find a tag of given type
-fn other_tag(tag: ExifTag, entries: &[ExifEntry]) -> Option<&ExifEntry>
+/// Find a tag of given type, within the same IFD
+fn other_tag(tag: ExifTag, kind: IfdKind, entries: &[ExifEntry]) -> Option<&ExifEntry>
 {
-	for entry in entries {
-		if entry.tag == tag {
-			return Some(entry);
-		}
-	}
-	None
+	entries.iter().find(|entry| entry.tag == tag && entry.ifd_kind == kind)
 }
 
 /// Does postprocessing in tags that depend on other tags to have a complete interpretation
 /// e.g. when the unit of a tag is annotated on another tag
 pub fn exif_postprocessing(entry: &mut ExifEntry, entries: &[ExifEntry])
 {
+	let kind = entry.ifd_kind;
+
 	match entry.tag {
 
 	ExifTag::XResolution | ExifTag::YResolution => {
-		if let Some(f) = other_tag(ExifTag::ResolutionUnit, entries) {
+		if let Some(f) = other_tag(ExifTag::ResolutionUnit, kind, entries) {
 			entry.unit = f.value_more_readable.clone();
 			entry.value_more_readable.push_str(" pixels per ");
 			entry.value_more_readable.push_str(&f.value_more_readable);
@@ -26,7 +23,7 @@ pub fn exif_postprocessing(entry: &mut ExifEntry, entries: &[ExifEntry])
 	},
 
 	ExifTag::FocalPlaneXResolution | ExifTag::FocalPlaneYResolution => {
-		if let Some(f) = other_tag(ExifTag::FocalPlaneResolutionUnit, entries) {
+		if let Some(f) = other_tag(ExifTag::FocalPlaneResolutionUnit, kind, entries) {
 			entry.unit = f.value_more_readable.clone();
 			entry.value_more_readable.push_str(" pixels per ");
 			entry.value_more_readable.push_str(&f.value_more_readable);
@@ -34,24 +31,24 @@ pub fn exif_postprocessing(entry: &mut ExifEntry, entries: &[ExifEntry])
 	},
 
 	ExifTag::GPSLatitude => {
-		if let Some(f) = other_tag(ExifTag::GPSLatitudeRef, entries) {
-			entry.value_more_readable.push_str(" ");
+		if let Some(f) = other_tag(ExifTag::GPSLatitudeRef, kind, entries) {
+			entry.value_more_readable.push(' ');
 			entry.value_more_readable.push_str(&f.value_more_readable);
 		}
 	},
 
 	ExifTag::GPSLongitude => {
-		if let Some(f) = other_tag(ExifTag::GPSLongitudeRef, entries) {
-			entry.value_more_readable.push_str(" ");
+		if let Some(f) = other_tag(ExifTag::GPSLongitudeRef, kind, entries) {
+			entry.value_more_readable.push(' ');
 			entry.value_more_readable.push_str(&f.value_more_readable);
 		}
 	},
 
 	ExifTag::GPSAltitude => {
-		if let Some(f) = other_tag(ExifTag::GPSAltitudeRef, entries) {
+		if let Some(f) = other_tag(ExifTag::GPSAltitudeRef, kind, entries) {
 			let altref = match f.value {
 				TagValue::U8(ref fv) => fv[0],
-				_ => return ()
+				_ => return
 			};
 
 			if altref != 0 {
@@ -61,31 +58,31 @@ pub fn exif_postprocessing(entry: &mut ExifEntry, entries: &[ExifEntry])
 	},
 
 	ExifTag::GPSDestLatitude => {
-		if let Some(f) = other_tag(ExifTag::GPSDestLatitudeRef, entries) {
-			entry.value_more_readable.push_str(" ");
+		if let Some(f) = other_tag(ExifTag::GPSDestLatitudeRef, kind, entries) {
+			entry.value_more_readable.push(' ');
 			entry.value_more_readable.push_str(&f.value_more_readable);
 		}
 	},
 
 	ExifTag::GPSDestLongitude => {
-		if let Some(f) = other_tag(ExifTag::GPSDestLongitudeRef, entries) {
-			entry.value_more_readable.push_str(" ");
+		if let Some(f) = other_tag(ExifTag::GPSDestLongitudeRef, kind, entries) {
+			entry.value_more_readable.push(' ');
 			entry.value_more_readable.push_str(&f.value_more_readable);
 		}
 	},
 
 	ExifTag::GPSDestDistance => {
-		if let Some(f) = other_tag(ExifTag::GPSDestDistanceRef, entries) {
+		if let Some(f) = other_tag(ExifTag::GPSDestDistanceRef, kind, entries) {
 			entry.unit = f.value_more_readable.clone();
-			entry.value_more_readable.push_str(" ");
+			entry.value_more_readable.push(' ');
 			entry.value_more_readable.push_str(&f.value_more_readable);
 		}
 	},
 
 	ExifTag::GPSSpeed => {
-		if let Some(f) = other_tag(ExifTag::GPSSpeedRef, entries) {
+		if let Some(f) = other_tag(ExifTag::GPSSpeedRef, kind, entries) {
 			entry.unit = f.value_more_readable.clone();
-			entry.value_more_readable.push_str(" ");
+			entry.value_more_readable.push(' ');
 			entry.value_more_readable.push_str(&f.value_more_readable);
 		}
 	},
@@ -2,7 +2,15 @@ use super::types::*;
 use super::ifdformat::*;
 use super::lowlevel::read_u16_array;
 
-static INV: &'static str = "Invalid data for this tag";
+static INV: &str = "Invalid data for this tag";
+
+/// Fallback for readable value functions that got a value of unexpected type.
+/// It should not happen since the parser checks formats, but images are
+/// hostile input, and a bad tag must never bring the whole parsing down.
+fn invalid(e: &TagValue) -> String
+{
+	format!("{} ({})", INV, e)
+}
 
 /// No-op for readable value tag function. Should not be used by any EXIF tag descriptor,
 /// except for the catch-all match that handles unknown tags
@@ -33,10 +41,132 @@ pub fn sensitivity_type(e: &TagValue) -> String {
 			n => return format!("Unknown ({})", n),
 		}.to_owned()
 	} else {
-		panic!(INV);
+		return invalid(e);
+	}
+}
+
+/// Readable form of long lists of values (e.g. strip offsets), that would be
+/// too verbose if printed in full
+pub fn value_list(e: &TagValue) -> String
+{
+	match *e {
+		TagValue::U32(ref v) => {
+			if v.len() == 1 {
+				format!("{}", v[0])
+			} else {
+				format!("{} values", v.len())
+			}
+		},
+		_ => return invalid(e),
+	}
+}
+
+pub fn new_subfile_type(e: &TagValue) -> String
+{
+	match *e {
+		TagValue::U32(ref v) => {
+			let n = v[0];
+			let mut s = Vec::new();
+			if (n & 1) > 0 {
+				s.push("Reduced resolution");
+			}
+			if (n & 2) > 0 {
+				s.push("Page of multi-page image");
+			}
+			if (n & 4) > 0 {
+				s.push("Transparency mask");
+			}
+			if s.is_empty() {
+				s.push("Full resolution image");
+			}
+			s.join(", ")
+		},
+		_ => return invalid(e),
 	}
 }
 
+pub fn photometric_interpretation(e: &TagValue) -> String
+{
+	let s = match *e {
+		TagValue::U16(ref v) => {
+			let n = v[0];
+			match n {
+				0 => "WhiteIsZero",
+				1 => "BlackIsZero",
+				2 => "RGB",
+				3 => "RGB palette",
+				4 => "Transparency mask",
+				5 => "CMYK",
+				6 => "YCbCr",
+				8 => "CIELab",
+				9 => "ICCLab",
+				10 => "ITULab",
+				32803 => "Color filter array",
+				34892 => "Linear raw",
+				_ => return format!("Unknown ({})", n),
+			}
+		},
+		_ => return invalid(e),
+	};
+
+	return s.to_string();
+}
+
+pub fn planar_configuration(e: &TagValue) -> String
+{
+	let s = match *e {
+		TagValue::U16(ref v) => {
+			let n = v[0];
+			match n {
+				1 => "Chunky",
+				2 => "Planar",
+				_ => return format!("Unknown ({})", n),
+			}
+		},
+		_ => return invalid(e),
+	};
+
+	return s.to_string();
+}
+
+pub fn page_number(e: &TagValue) -> String
+{
+	match *e {
+		TagValue::U16(ref v) => {
+			if v[1] == 0 {
+				format!("Page {}", v[0] as u32 + 1)
+			} else {
+				format!("Page {} of {}", v[0] as u32 + 1, v[1])
+			}
+		},
+		_ => return invalid(e),
+	}
+}
+
+pub fn compression(e: &TagValue) -> String
+{
+	let s = match *e {
+		TagValue::U16(ref v) => {
+			let n = v[0];
+			match n {
+				1 => "Uncompressed",
+				2 => "CCITT 1D",
+				3 => "T4/Group 3 Fax",
+				4 => "T6/Group 4 Fax",
+				5 => "LZW",
+				6 => "JPEG (old-style)",
+				7 => "JPEG",
+				8 => "Adobe Deflate",
+				32773 => "PackBits",
+				_ => return format!("Unknown ({})", n),
+			}
+		},
+		_ => return invalid(e),
+	};
+
+	return s.to_string();
+}
+
 pub fn orientation(e: &TagValue) -> String
 {
 	let s = match *e {
@@ -51,7 +181,7 @@ pub fn orientation(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -66,7 +196,7 @@ pub fn rational_value(e: &TagValue) -> String
 		TagValue::IRational(ref v) => {
 			format!("{}", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -79,7 +209,7 @@ pub fn rational_values(e: &TagValue) -> String
 			let ve: Vec<f64> = v.iter().map(|&x| x.value()).collect();
 			numarray_to_string(&ve)
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -97,7 +227,7 @@ pub fn resolution_unit(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -119,7 +249,7 @@ pub fn exposure_time(e: &TagValue) -> String
 				format!("{:.1} s", r.value())
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -131,7 +261,7 @@ pub fn f_number(e: &TagValue) -> String
 		TagValue::URational(ref v) => {
 			format!("f/{:.1}", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -154,7 +284,7 @@ pub fn exposure_program(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -166,7 +296,7 @@ pub fn focal_length(e: &TagValue) -> String
 		TagValue::URational(ref v) => {
 			format!("{} mm", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -178,7 +308,7 @@ pub fn focal_length_35(e: &TagValue) -> String
 		TagValue::U16(ref v) => {
 			format!("{} mm", v[0])
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -190,7 +320,7 @@ pub fn meters(e: &TagValue) -> String
 		TagValue::URational(ref v) => {
 			format!("{:.1} m", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -205,10 +335,10 @@ pub fn iso_speeds(e: &TagValue) -> String
 		} else if v.len() == 2 || v.len() == 3 {
 			format!("ISO {} latitude {}", v[0], v[1])
 		} else {
-			format!("Unknown ({})", numarray_to_string(&v))
+			format!("Unknown ({})", numarray_to_string(v))
 		}
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -230,7 +360,7 @@ pub fn dms(e: &TagValue) -> String
 			format!("{:.7}°", deg.value() + min.value() / 60.0 + sec.value() / 3600.0)
 		}
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -247,7 +377,7 @@ pub fn gps_alt_ref(e: &TagValue) -> String
 				_ => return format!("Unknown, assumed below sea level ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -267,7 +397,7 @@ pub fn gpsdestdistanceref(e: &TagValue) -> String
 			return format!("Unknown ({})", v)
 		}
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -279,7 +409,7 @@ pub fn gpsdestdistance(e: &TagValue) -> String
 		TagValue::URational(ref v) => {
 			format!("{:.3}", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -299,7 +429,7 @@ pub fn gpsspeedref(e: &TagValue) -> String
 			return format!("Unknown ({})", v)
 		}
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -311,7 +441,7 @@ pub fn gpsspeed(e: &TagValue) -> String
 		TagValue::URational(ref v) => {
 			format!("{:.1}", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -329,7 +459,7 @@ pub fn gpsbearingref(e: &TagValue) -> String
 			return format!("Unknown ({})", v)
 		}
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -341,7 +471,7 @@ pub fn gpsbearing(e: &TagValue) -> String
 		TagValue::URational(ref v) => {
 			format!("{:.2}°", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -356,7 +486,7 @@ pub fn gpstimestamp(e: &TagValue) -> String
 		let sec = v[2];
 		format!("{:02.0}:{:02.0}:{:04.1} UTC", hour.value(), min.value(), sec.value())
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -373,7 +503,7 @@ pub fn gpsdiff(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -391,7 +521,7 @@ pub fn gpsstatus(e: &TagValue) -> String
 			return format!("Unknown ({})", v)
 		}
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -409,7 +539,27 @@ pub fn gpsmeasuremode(e: &TagValue) -> String
 			return format!("Unknown ({})", v)
 		}
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
+	};
+
+	return s.to_string();
+}
+
+pub fn interop_index(e: &TagValue) -> String
+{
+	let s = match *e {
+	TagValue::Ascii(ref v) => {
+		if v == "R98" {
+			"R98 - DCF basic file (sRGB)"
+		} else if v == "R03" {
+			"R03 - DCF option file (Adobe RGB)"
+		} else if v == "THM" {
+			"THM - DCF thumbnail file"
+		} else {
+			return format!("Unknown ({})", v)
+		}
+	},
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -424,7 +574,7 @@ pub fn undefined_as_ascii(e: &TagValue) -> String
 	TagValue::Undefined(ref v, _) => {
 		String::from_utf8_lossy(&v[..])
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -438,7 +588,7 @@ pub fn undefined_as_u8(e: &TagValue) -> String
 	TagValue::Undefined(ref v, _) => {
 		numarray_to_string(v)
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -477,7 +627,7 @@ pub fn undefined_as_encoded_string(e: &TagValue) -> String
 			format!("String w/ undefined encoding {}", numarray_to_string(v))
 		}
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	}
 }
 
@@ -488,7 +638,7 @@ pub fn undefined_as_blob(e: &TagValue) -> String
 	TagValue::Undefined(ref v, _) => {
 		format!("Blob of {} bytes", v.len())
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -500,7 +650,7 @@ pub fn apex_tv(e: &TagValue) -> String
 		TagValue::IRational(ref v) => {
 			format!("{:.1} Tv APEX", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	}
 }
 
@@ -510,7 +660,7 @@ pub fn apex_av(e: &TagValue) -> String
 		TagValue::URational(ref v) => {
 			format!("{:.1} Av APEX", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	}
 }
 
@@ -525,7 +675,7 @@ pub fn apex_brightness(e: &TagValue) -> String
 				format!("{:.1} APEX", v[0].value())
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	}
 }
 
@@ -535,7 +685,7 @@ pub fn apex_ev(e: &TagValue) -> String
 		TagValue::IRational(ref v) => {
 			format!("{:.2} EV APEX", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	}
 }
 
@@ -543,13 +693,13 @@ pub fn file_source(e: &TagValue) -> String
 {
 	let s = match *e {
 	TagValue::Undefined(ref v, _) => {
-		if v.len() > 0 && v[0] == 3 {
+		if !v.is_empty() && v[0] == 3 {
 			"DSC"
 		} else {
 			"Unknown"
 		}
 	},
-	_ => panic!(INV),
+	_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -561,7 +711,7 @@ pub fn flash_energy(e: &TagValue) -> String
 		TagValue::URational(ref v) => {
 			format!("{} BCPS", v[0].value())
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	}
 }
 
@@ -582,7 +732,7 @@ pub fn metering_mode(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -618,7 +768,7 @@ pub fn light_source(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -635,7 +785,7 @@ pub fn color_space(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -684,7 +834,7 @@ pub fn flash(e: &TagValue) -> String
 
 			format!("{}{}{}{}", b0, b12, b34, b6)
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	}
 }
 
@@ -699,7 +849,7 @@ pub fn subject_area(e: &TagValue) -> String
 			_ => format!("Unknown ({}) ", numarray_to_string(v)),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	}
 }
 
@@ -709,7 +859,7 @@ pub fn subject_location(e: &TagValue) -> String
 		TagValue::U16(ref v) => {
 			format!("at pixel {},{}", v[0], v[1])
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	}
 }
 
@@ -725,7 +875,7 @@ pub fn sharpness(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -743,7 +893,7 @@ pub fn saturation(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -761,7 +911,7 @@ pub fn contrast(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -781,7 +931,7 @@ pub fn gain_control(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -799,7 +949,7 @@ pub fn exposure_mode(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -818,7 +968,7 @@ pub fn scene_capture_type(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -834,7 +984,7 @@ pub fn scene_type(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -851,7 +1001,7 @@ pub fn white_balance_mode(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -873,7 +1023,7 @@ pub fn sensing_method(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -890,7 +1040,7 @@ pub fn custom_rendered(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -909,7 +1059,7 @@ pub fn subject_distance_range(e: &TagValue) -> String
 				_ => return format!("Unknown ({})", n),
 			}
 		},
-		_ => panic!(INV),
+		_ => return invalid(e),
 	};
 
 	return s.to_string();
@@ -939,7 +1089,7 @@ pub fn lens_spec(e: &TagValue) -> String
 		}
 	},
 
-	_ => panic!(INV),
+	_ => return invalid(e),
 
 	}
 }
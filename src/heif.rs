@@ -0,0 +1,381 @@
+use super::types::ExifError;
+use super::lowlevel::slice_at;
+use super::source::ByteSource;
+use super::bmff::*;
+
+/// Largest "meta" box accepted. It is read whole into memory, and normally
+/// takes a few kilobytes.
+const MAX_META_SIZE: usize = 16 * 1024 * 1024;
+
+/// Largest Exif or XMP item accepted
+const MAX_ITEM_SIZE: usize = 16 * 1024 * 1024;
+
+/// Content type of the "mime" item that holds the XMP packet
+const XMP_CONTENT_TYPE: &[u8] = b"application/rdf+xml";
+
+/// Location of an item, as described by the "iloc" box
+struct ItemLocation {
+	item_id: u32,
+	/// 0 = offsets within the file, 1 = offsets within the "idat" box
+	construction_method: u16,
+	/// (offset, length) of each extent. A zero length means "up to the end".
+	extents: Vec<(usize, usize)>,
+}
+
+fn heif_error(s: &str) -> ExifError
+{
+	ExifError::HeifWithoutExif(s.to_string())
+}
+
+/// Gets the primary item ID from the "pitm" box
+fn parse_pitm(data: &[u8]) -> Option<u32>
+{
+	let mut r = FieldReader::new(data, 0);
+	let version = r.read(1)?;
+	r.read(3)?;
+	return r.read_short_or_long(version != 0).map(|id| id as u32);
+}
+
+/// Gets the IDs of the items of a given type from the "iinf" box. For "mime"
+/// items, the content type may be required as well.
+fn parse_iinf(data: &[u8], item_type: &[u8], content_type: Option<&[u8]>) -> Option<Vec<u32>>
+{
+	let mut r = FieldReader::new(data, 0);
+	let version = r.read(1)?;
+	r.read(3)?;
+	r.read_short_or_long(version != 0)?;
+
+	let mut ids: Vec<u32> = Vec::new();
+	for infe in read_boxes_in(data, r.pos) {
+		if infe.box_type != *b"infe" {
+			continue;
+		}
+		let infe_data = &data[infe.offset..infe.offset + infe.size];
+		let mut r = FieldReader::new(infe_data, 0);
+		let version = r.read(1)?;
+		r.read(3)?;
+		if version < 2 {
+			// versions 0 and 1 have no item type
+			continue;
+		}
+		let id = r.read_short_or_long(version != 2)? as u32;
+		let _protection_index = r.read(2)?;
+		if r.read_fourcc()?[..] != *item_type {
+			continue;
+		}
+		if let Some(content_type) = content_type {
+			// item name and content type are null-terminated strings
+			let mut strings = infe_data[r.pos..].split(|&c| c == 0);
+			strings.next();
+			if strings.next() != Some(content_type) {
+				continue;
+			}
+		}
+		ids.push(id);
+	}
+
+	return Some(ids);
+}
+
+/// Lists the item locations of the "iloc" box
+fn parse_iloc(data: &[u8]) -> Option<Vec<ItemLocation>>
+{
+	let mut r = FieldReader::new(data, 0);
+	let version = r.read(1)?;
+	r.read(3)?;
+
+	let sizes = r.read(1)?;
+	let offset_size = (sizes >> 4) as usize;
+	let length_size = (sizes & 0xf) as usize;
+	let sizes = r.read(1)?;
+	let base_offset_size = (sizes >> 4) as usize;
+	let index_size = if version == 1 || version == 2 {(sizes & 0xf) as usize} else {0};
+
+	let item_count = r.read_short_or_long(version == 2)?;
+
+	let mut locations: Vec<ItemLocation> = Vec::new();
+	for _ in 0..item_count {
+		let item_id = r.read_short_or_long(version == 2)? as u32;
+		let construction_method = if version == 1 || version == 2 {
+			(r.read(2)? & 0xf) as u16
+		} else {
+			0
+		};
+		let _data_reference_index = r.read(2)?;
+		let base_offset = r.read(base_offset_size)?;
+		let extent_count = r.read(2)?;
+		if offset_size + length_size + index_size == 0 && extent_count > 1 {
+			// extents that take no room, a hostile file could make us loop for long
+			return None;
+		}
+
+		let mut extents: Vec<(usize, usize)> = Vec::new();
+		for _ in 0..extent_count {
+			let _extent_index = r.read(index_size)?;
+			let offset = base_offset.checked_add(r.read(offset_size)?)?;
+			let length = r.read(length_size)?;
+			extents.push((offset as usize, length as usize));
+		}
+
+		locations.push(ItemLocation { item_id, construction_method, extents });
+	}
+
+	return Some(locations);
+}
+
+/// Gets the IDs of the items that `from_id` refers to with a given type of
+/// reference, from the "iref" box
+fn parse_iref(data: &[u8], reference_type: &[u8], from_id: u32) -> Option<Vec<u32>>
+{
+	let mut r = FieldReader::new(data, 0);
+	let version = r.read(1)?;
+	r.read(3)?;
+
+	let mut ids: Vec<u32> = Vec::new();
+	for reference in read_boxes_in(data, r.pos) {
+		if reference.box_type[..] != *reference_type {
+			continue;
+		}
+		let mut r = FieldReader::new(&data[reference.offset..reference.offset + reference.size], 0);
+		if r.read_short_or_long(version != 0)? as u32 != from_id {
+			continue;
+		}
+		let count = r.read(2)?;
+		for _ in 0..count {
+			ids.push(r.read_short_or_long(version != 0)? as u32);
+		}
+	}
+
+	return Some(ids);
+}
+
+/// Reads the data of an item of a HEIF image, of a given type, and for "mime"
+/// items of a given content type. The "meta" box lists the items, and where
+/// their data is. When there are several, the one that describes the primary
+/// image is preferred. `name` tells the item in error messages.
+fn read_heif_item<S: ByteSource>(contents: &mut S, item_type: &[u8], content_type: Option<&[u8]>, name: &str)
+					-> Result<Vec<u8>, ExifError>
+{
+	let size = contents.size();
+	let top = read_boxes(contents, 0, size);
+	let meta = find_box(&top, b"meta").ok_or_else(|| heif_error("No meta box"))?;
+	if meta.size > MAX_META_SIZE {
+		return Err(heif_error("meta box too big"));
+	}
+	let meta = contents.read_at(meta.offset, meta.size).ok_or_else(|| heif_error("meta box truncated"))?;
+
+	// meta is a FullBox, children come after version and flags
+	let children = read_boxes_in(&meta, 4);
+	let child = |box_type: &[u8]| find_box(&children, box_type).map(|b| &meta[b.offset..b.offset + b.size]);
+
+	let iinf = child(b"iinf").ok_or_else(|| heif_error("No iinf box"))?;
+	let item_ids = parse_iinf(iinf, item_type, content_type).ok_or_else(|| heif_error("iinf box truncated"))?;
+	if item_ids.is_empty() {
+		return Err(heif_error(&format!("No {} item", name)));
+	}
+
+	// item that refers to the primary image (content description reference)
+	let primary = child(b"pitm").and_then(parse_pitm);
+	let mut item_id = item_ids[0];
+	if let (Some(primary), Some(iref)) = (primary, child(b"iref")) {
+		for &id in &item_ids {
+			if parse_iref(iref, b"cdsc", id).unwrap_or_default().contains(&primary) {
+				item_id = id;
+				break;
+			}
+		}
+	}
+
+	let iloc = child(b"iloc").ok_or_else(|| heif_error("No iloc box"))?;
+	let locations = parse_iloc(iloc).ok_or_else(|| heif_error("iloc box truncated"))?;
+	let location = locations.iter().find(|l| l.item_id == item_id)
+		.ok_or_else(|| heif_error(&format!("{} item location not found", name)))?;
+
+	let idat = match location.construction_method {
+		0 => None,
+		1 => Some(child(b"idat").ok_or_else(|| heif_error("No idat box"))?),
+		_ => return Err(heif_error(&format!("Unsupported {} item construction method", name))),
+	};
+	let available = idat.map_or(size, |idat| idat.len());
+
+	let mut data: Vec<u8> = Vec::new();
+	for &(offset, length) in &location.extents {
+		let length = if length == 0 {available.saturating_sub(offset)} else {length};
+		// checked before reading, since a zero length may take the whole file
+		if data.len().saturating_add(length) > MAX_ITEM_SIZE {
+			return Err(heif_error(&format!("{} item too big", name)));
+		}
+		let extent = match idat {
+			None => contents.read_at(offset, length),
+			Some(idat) => slice_at(idat, offset, length).map(|extent| extent.to_vec()),
+		};
+		data.extend(extent.ok_or_else(|| heif_error(&format!("{} item goes beyond EOF", name)))?);
+	}
+
+	return Ok(data);
+}
+
+/// Find the EXIF data (a TIFF block) in a HEIF image (HEIC, AVIF), stored in an
+/// item of type "Exif". The item data starts with the offset of the TIFF header
+/// (exif_tiff_header_offset), normally after an "Exif\0\0" prefix.
+pub fn find_exif_in_heif<S: ByteSource>(contents: &mut S) -> Result<Vec<u8>, ExifError>
+{
+	let mut data = read_heif_item(contents, b"Exif", None, "Exif")?;
+
+	// skip exif_tiff_header_offset and the prefix it measures
+	let mut r = FieldReader::new(&data, 0);
+	let tiff_offset = r.read(4).ok_or_else(|| heif_error("Exif item truncated"))? as usize;
+	if tiff_offset > data.len() - 4 {
+		return Err(heif_error("Exif item truncated"));
+	}
+	data.drain(..4 + tiff_offset);
+
+	return Ok(data);
+}
+
+/// Find the XMP packet of a HEIF image (HEIC, AVIF), stored in an item of type
+/// "mime" with content type "application/rdf+xml".
+/// Returns None if there is no such item.
+pub fn find_xmp_in_heif<S: ByteSource>(contents: &mut S) -> Option<Vec<u8>>
+{
+	read_heif_item(contents, b"mime", Some(XMP_CONTENT_TYPE), "XMP").ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testutil::*;
+
+	/// Item of a test image: ID, type, name and content type (null-terminated), data
+	type Item<'a> = (u16, &'a [u8], &'a [u8], &'a [u8]);
+
+	/// HEIF image with the given items, whose data is stored in the "mdat" box,
+	/// or in the "idat" box of "meta"
+	fn heif(items: &[Item], primary: u16, iref: &[u8], in_idat: bool) -> Vec<u8>
+	{
+		let ftyp = bmff_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+
+		let infes: Vec<u8> = items.iter().flat_map(|&(id, item_type, names, _)| {
+			full_box(b"infe", 2, &[&id.to_be_bytes()[..], &[0, 0], item_type, names].concat())
+		}).collect();
+		let iinf = full_box(b"iinf", 0, &[&(items.len() as u16).to_be_bytes()[..], &infes].concat());
+		let pitm = full_box(b"pitm", 0, &primary.to_be_bytes());
+		let data: Vec<u8> = items.iter().flat_map(|item| item.3.to_vec()).collect();
+
+		let meta = |base: usize| {
+			let mut iloc = vec![0x44, 0x00];
+			iloc.extend_from_slice(&(items.len() as u16).to_be_bytes());
+			let mut offset = base;
+			for &(id, _, _, data) in items {
+				iloc.extend_from_slice(&id.to_be_bytes());
+				iloc.extend_from_slice(&[0, if in_idat {1} else {0}, 0, 0, 0, 1]);
+				iloc.extend_from_slice(&(offset as u32).to_be_bytes());
+				iloc.extend_from_slice(&(data.len() as u32).to_be_bytes());
+				offset += data.len();
+			}
+			let mut children = [
+				full_box(b"hdlr", 0, b"\0\0\0\0pict\0\0\0\0\0\0\0\0\0\0\0\0\0"),
+				pitm.clone(), iinf.clone(), full_box(b"iloc", 1, &iloc),
+			].concat();
+			if !iref.is_empty() {
+				children.extend(full_box(b"iref", 0, iref));
+			}
+			if in_idat {
+				children.extend(bmff_box(b"idat", &data));
+			}
+			return full_box(b"meta", 0, &children);
+		};
+
+		if in_idat {
+			return [ftyp, meta(0)].concat();
+		}
+		let base = ftyp.len() + meta(0).len() + 8;
+		return [ftyp, meta(base), bmff_box(b"mdat", &data)].concat();
+	}
+
+	const TIFF: &[u8] = b"II\x2a\0\x08\0\0\0\0\0\0\0\0\0";
+
+	fn exif_payload(tiff: &[u8]) -> Vec<u8>
+	{
+		return [&[0, 0, 0, 6][..], b"Exif\0\0", tiff].concat();
+	}
+
+	#[test]
+	fn exif_item() {
+		let payload = exif_payload(TIFF);
+		for &in_idat in &[false, true] {
+			let image = heif(&[(1, b"hvc1", b"\0", b"image"), (2, b"Exif", b"\0", &payload)], 1, b"", in_idat);
+			assert_eq!(find_exif_in_heif(&mut &image[..]).unwrap(), TIFF);
+		}
+
+		let image = heif(&[(1, b"hvc1", b"\0", b"image")], 1, b"", false);
+		assert!(find_exif_in_heif(&mut &image[..]).is_err());
+	}
+
+	#[test]
+	fn exif_of_primary_image() {
+		let thumbnail = exif_payload(b"II\x2a\0\x08\0\0\0thumbnail");
+		let payload = exif_payload(TIFF);
+		let items: [Item; 4] = [
+			(1, b"hvc1", b"\0", b"thumbnail"),
+			(2, b"hvc1", b"\0", b"image"),
+			(3, b"Exif", b"\0", &thumbnail),
+			(4, b"Exif", b"\0", &payload),
+		];
+		// Exif item 3 describes item 1, and 4 describes item 2
+		let cdsc = [bmff_box(b"cdsc", &[0, 3, 0, 1, 0, 1]), bmff_box(b"cdsc", &[0, 4, 0, 1, 0, 2])].concat();
+		let image = heif(&items, 2, &cdsc, false);
+		assert_eq!(find_exif_in_heif(&mut &image[..]).unwrap(), TIFF);
+		let image = heif(&items, 1, &cdsc, false);
+		assert_eq!(find_exif_in_heif(&mut &image[..]).unwrap(), &thumbnail[10..]);
+	}
+
+	#[test]
+	fn xmp_item() {
+		let image = heif(&[
+			(1, b"hvc1", b"\0", b"image"),
+			(2, b"mime", b"\0text/plain\0", b"not XMP"),
+			(3, b"mime", b"XMP\0application/rdf+xml\0", b"<x:xmpmeta/>"),
+		], 1, b"", false);
+		assert_eq!(find_xmp_in_heif(&mut &image[..]).unwrap(), b"<x:xmpmeta/>");
+	}
+
+	/// Source of a given size, whose bytes past the image are zeros. Large reads fail.
+	struct Padded<'a> {
+		image: &'a [u8],
+		size: usize,
+	}
+
+	impl<'a> ByteSource for Padded<'a> {
+		fn size(&self) -> usize
+		{
+			self.size
+		}
+
+		fn read_at(&mut self, offset: usize, length: usize) -> Option<Vec<u8>>
+		{
+			assert!(length <= MAX_ITEM_SIZE, "read of {} bytes", length);
+			let mut data = vec![0; length];
+			for (i, byte) in data.iter_mut().enumerate() {
+				*byte = *self.image.get(offset + i).unwrap_or(&0);
+			}
+			return Some(data);
+		}
+	}
+
+	#[test]
+	fn extent_up_to_eof() {
+		let payload = exif_payload(TIFF);
+		let mut image = heif(&[(1, b"hvc1", b"\0", b"image"), (2, b"Exif", b"\0", &payload)], 1, b"", false);
+		// zero length of the Exif extent, in the last item of "iloc"
+		let iloc = image.windows(4).position(|w| w == b"iloc").unwrap();
+		let length = iloc + 4 + 4 + 4 + 2 * 16 - 4;
+		image[length..length + 4].copy_from_slice(&[0; 4]);
+
+		let data = find_exif_in_heif(&mut &image[..]).unwrap();
+		assert!(data.starts_with(TIFF));
+
+		let mut source = Padded { image: &image, size: 2 * MAX_ITEM_SIZE };
+		assert!(find_exif_in_heif(&mut source).is_err());
+	}
+}
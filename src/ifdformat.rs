@@ -1,11 +1,13 @@
 use std::fmt::Display;
+use std::convert::TryFrom;
 use super::types::*;
+use super::rational::*;
 use super::lowlevel::*;
 
 /// generic function that prints a string representation of a vector
 pub fn numarray_to_string<T: Display>(numbers: &[T]) -> String
 {
-	if numbers.len() < 1 {
+	if numbers.is_empty() {
 		return "".to_string();
 	} else if numbers.len() == 1 {
 		return format!("{}", &numbers[0]);
@@ -15,7 +17,7 @@ pub fn numarray_to_string<T: Display>(numbers: &[T]) -> String
 	let mut first = true;
 	for number in numbers {
 		if !first {
-			s = s + ", ";
+			s += ", ";
 		}
 		first = false;
 		let s2 = format!("{}", number);
@@ -41,7 +43,7 @@ pub fn tag_value_new(f: &IfdEntry) -> TagValue
 			TagValue::Ascii(s.to_string())
 		},
 		IfdFormat::U16 => {
-			if f.data.len() < (f.count as usize * 2) {
+			if f.data.len() < (f.count as usize).saturating_mul(2) {
 				return TagValue::Invalid(f.data.clone(), f.le,
 							             f.format as u16, f.count);
 			}
@@ -49,7 +51,7 @@ pub fn tag_value_new(f: &IfdEntry) -> TagValue
 			TagValue::U16(a)
 		},
 		IfdFormat::I16 => {
-			if f.data.len() < (f.count as usize * 2) {
+			if f.data.len() < (f.count as usize).saturating_mul(2) {
 				return TagValue::Invalid(f.data.clone(), f.le,
 							             f.format as u16, f.count);
 			}
@@ -57,7 +59,7 @@ pub fn tag_value_new(f: &IfdEntry) -> TagValue
 			TagValue::I16(a)
 		},
 		IfdFormat::U8 => {
-			if f.data.len() < (f.count as usize * 1) {
+			if f.data.len() < (f.count as usize) {
 				return TagValue::Invalid(f.data.clone(), f.le,
 							             f.format as u16, f.count);
 			}
@@ -65,15 +67,15 @@ pub fn tag_value_new(f: &IfdEntry) -> TagValue
 			TagValue::U8(a)
 		},
 		IfdFormat::I8 => {
-			if f.data.len() < (f.count as usize * 1) {
+			if f.data.len() < (f.count as usize) {
 				return TagValue::Invalid(f.data.clone(), f.le,
 							             f.format as u16, f.count);
 			}
 			let a = read_i8_array(f.count, &f.data[..]);
 			TagValue::I8(a)
 		},
-		IfdFormat::U32 => {
-			if f.data.len() < (f.count as usize * 4) {
+		IfdFormat::U32 | IfdFormat::Ifd => {
+			if f.data.len() < (f.count as usize).saturating_mul(4) {
 				return TagValue::Invalid(f.data.clone(), f.le,
 							             f.format as u16, f.count);
 			}
@@ -81,15 +83,31 @@ pub fn tag_value_new(f: &IfdEntry) -> TagValue
 			TagValue::U32(a)
 		},
 		IfdFormat::I32 => {
-			if f.data.len() < (f.count as usize * 4) {
+			if f.data.len() < (f.count as usize).saturating_mul(4) {
 				return TagValue::Invalid(f.data.clone(), f.le,
 							             f.format as u16, f.count);
 			}
 			let a = read_i32_array(f.le, f.count, &f.data[..]);
 			TagValue::I32(a)
 		},
+		IfdFormat::U64 | IfdFormat::Ifd8 => {
+			if f.data.len() < (f.count as usize).saturating_mul(8) {
+				return TagValue::Invalid(f.data.clone(), f.le,
+							             f.format as u16, f.count);
+			}
+			let a = read_u64_array(f.le, f.count, &f.data[..]);
+			TagValue::U64(a)
+		},
+		IfdFormat::I64 => {
+			if f.data.len() < (f.count as usize).saturating_mul(8) {
+				return TagValue::Invalid(f.data.clone(), f.le,
+							             f.format as u16, f.count);
+			}
+			let a = read_i64_array(f.le, f.count, &f.data[..]);
+			TagValue::I64(a)
+		},
 		IfdFormat::F32 => {
-			if f.data.len() < (f.count as usize * 4) {
+			if f.data.len() < (f.count as usize).saturating_mul(4) {
 				return TagValue::Invalid(f.data.clone(), f.le,
 							             f.format as u16, f.count);
 			}
@@ -97,7 +115,7 @@ pub fn tag_value_new(f: &IfdEntry) -> TagValue
 			TagValue::F32(a)
 		},
 		IfdFormat::F64 => {
-			if f.data.len() < (f.count as usize * 8) {
+			if f.data.len() < (f.count as usize).saturating_mul(8) {
 				return TagValue::Invalid(f.data.clone(), f.le,
 							             f.format as u16, f.count);
 			}
@@ -105,7 +123,7 @@ pub fn tag_value_new(f: &IfdEntry) -> TagValue
 			TagValue::F64(a)
 		},
 		IfdFormat::URational => {
-			if f.data.len() < (f.count as usize * 8) {
+			if f.data.len() < (f.count as usize).saturating_mul(8) {
 				return TagValue::Invalid(f.data.clone(), f.le,
 							             f.format as u16, f.count);
 			}
@@ -113,7 +131,7 @@ pub fn tag_value_new(f: &IfdEntry) -> TagValue
 			TagValue::URational(a)
 		},
 		IfdFormat::IRational => {
-			if f.data.len() < (f.count as usize * 8) {
+			if f.data.len() < (f.count as usize).saturating_mul(8) {
 				return TagValue::Invalid(f.data.clone(), f.le,
 							             f.format as u16, f.count);
 			}
@@ -130,3 +148,60 @@ pub fn tag_value_new(f: &IfdEntry) -> TagValue
 	}
 }
 
+
+/// Converts a vector of integers into another integer type, if every item fits
+fn convert_integers<T: TryFrom<i64>>(v: &[i64]) -> Option<Vec<T>>
+{
+	v.iter().map(|&n| T::try_from(n).ok()).collect()
+}
+
+/// Converts a numeric TagValue into another integer or rational format, e.g. a
+/// LONG into the SHORT specified by the standard. Integers may become rationals,
+/// but not the other way around. Returns None if the value has another format,
+/// or if any of the items does not fit in the new format.
+pub fn tag_value_coerce(value: &TagValue, format: IfdFormat) -> Option<TagValue>
+{
+	let mut integers: Vec<i64> = Vec::new();
+
+	let rationals: Vec<(i64, i64)> = match *value {
+		TagValue::U8(_) | TagValue::U16(_) | TagValue::U32(_) |
+		TagValue::I8(_) | TagValue::I16(_) | TagValue::I32(_) |
+		TagValue::U64(_) | TagValue::I64(_) => {
+			while let Some(n) = value.to_i64(integers.len()) {
+				integers.push(n);
+			}
+			integers.iter().map(|&n| (n, 1)).collect()
+		},
+		TagValue::URational(ref v) =>
+			v.iter().map(|r| (i64::from(r.numerator), i64::from(r.denominator))).collect(),
+		TagValue::IRational(ref v) =>
+			v.iter().map(|r| (i64::from(r.numerator), i64::from(r.denominator))).collect(),
+		_ => return None,
+	};
+
+	let is_integer = integers.len() == rationals.len();
+
+	let coerced = match format {
+		IfdFormat::U8 if is_integer => TagValue::U8(convert_integers(&integers)?),
+		IfdFormat::U16 if is_integer => TagValue::U16(convert_integers(&integers)?),
+		IfdFormat::U32 if is_integer => TagValue::U32(convert_integers(&integers)?),
+		IfdFormat::I8 if is_integer => TagValue::I8(convert_integers(&integers)?),
+		IfdFormat::I16 if is_integer => TagValue::I16(convert_integers(&integers)?),
+		IfdFormat::I32 if is_integer => TagValue::I32(convert_integers(&integers)?),
+		IfdFormat::URational => {
+			let numerators: Vec<u32> = convert_integers(&rationals.iter().map(|r| r.0).collect::<Vec<i64>>())?;
+			let denominators: Vec<u32> = convert_integers(&rationals.iter().map(|r| r.1).collect::<Vec<i64>>())?;
+			TagValue::URational(numerators.into_iter().zip(denominators)
+				.map(|(numerator, denominator)| URational { numerator, denominator }).collect())
+		},
+		IfdFormat::IRational => {
+			let numerators: Vec<i32> = convert_integers(&rationals.iter().map(|r| r.0).collect::<Vec<i64>>())?;
+			let denominators: Vec<i32> = convert_integers(&rationals.iter().map(|r| r.1).collect::<Vec<i64>>())?;
+			TagValue::IRational(numerators.into_iter().zip(denominators)
+				.map(|(numerator, denominator)| IRational { numerator, denominator }).collect())
+		},
+		_ => return None,
+	};
+
+	return Some(coerced);
+}
@@ -1,4 +1,36 @@
-use types::ExifError;
+use types::*;
+use lowlevel::read_u32;
+
+/// Header of the EXIF payload in a JPEG APP1 segment, that some APIs
+/// keep when they hand over the EXIF data alone
+pub const EXIF_PREFIX: &[u8] = b"Exif\0\0";
+
+/// Detect the type of an ISO-BMFF image (HEIF, AVIF, CR3) by the brands listed in the "ftyp" box
+fn detect_bmff_type(contents: &[u8]) -> &'static str
+{
+	let size = read_u32(false, &contents[0..4]) as usize;
+	let mut brands: Vec<&[u8]> = vec![&contents[8..12]];
+	let mut offset = 16;
+	while offset + 4 <= size.min(contents.len()) {
+		brands.push(&contents[offset..offset + 4]);
+		offset += 4;
+	}
+
+	// brands of a specific codec win over the generic HEIF brands
+	let mut mime = "";
+	for brand in brands {
+		match brand {
+			b"crx " => return "image/x-canon-cr3",
+			b"avif" | b"avis" => return "image/avif",
+			b"heic" | b"heix" | b"heim" | b"heis" |
+			b"hevc" | b"hevx" | b"hevm" | b"hevs" => mime = "image/heic",
+			b"mif1" | b"msf1" if mime.is_empty() => mime = "image/heif",
+			_ => {},
+		}
+	}
+
+	return mime;
+}
 
 /// Detect the type of an image contained in a byte buffer
 pub fn detect_type(contents: &[u8]) -> &str
@@ -7,19 +39,59 @@ pub fn detect_type(contents: &[u8]) -> &str
 		return "";
 	}
 
-	if contents[0] == 0xff && contents[1] == 0xd8 &&
-			contents[2] == 0xff && // contents[3] == 0xe0 &&
-			contents[6] == b'J' && contents[7] == b'F' &&
-			contents[8] == b'I' && contents[9] == b'F' &&
-			contents[10] == 0 {
-		return "image/jpeg";
+	if contents.starts_with(b"FUJIFILMCCD-RAW") {
+		return "image/x-fuji-raf";
+	}
+	if contents.len() >= 12 && contents[0..12] ==
+			[0, 0, 0, 12, b'J', b'X', b'L', b' ', 0x0d, 0x0a, 0x87, 0x0a] {
+		/* JPEG XL container */
+		return "image/jxl";
 	}
-	if contents[0] == 0xff && contents[1] == 0xd8 &&
-			contents[2] == 0xff && // contents[3] == 0xe0 &&
-			contents[6] == b'E' && contents[7] == b'x' &&
-			contents[8] == b'i' && contents[9] == b'f' &&
-			contents[10] == 0 {
-		return "image/jpeg";
+	if contents[0] == 0xff && contents[1] == 0x0a {
+		/* JPEG XL bare codestream. It has no room for metadata, and
+		   the parser fails with an error that says so. */
+		return "image/jxl";
+	}
+	if contents.len() >= 12 && contents[4..8] == b"ftyp"[..] {
+		return detect_bmff_type(contents);
+	}
+
+	if contents[0] == 0xff && contents[1] == 0xd8 && contents[2] == 0xff {
+		/* JPEG: SOI followed by another marker, after optional fill bytes,
+		   whatever the first segment is (JFIF, Exif, ICC profile, DQT...).
+		   RSTn, SOI and EOI cannot come first. */
+		match contents[3..].iter().find(|&&c| c != 0xff) {
+			Some(&marker) if marker >= 0xc0 && !(0xd0..=0xd9).contains(&marker) => return "image/jpeg",
+			_ => {},
+		}
+	}
+	if contents[0..8] == [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a] {
+		return "image/png";
+	}
+	if contents.len() >= 12 && contents[0..4] == b"RIFF"[..] && contents[8..12] == b"WEBP"[..] {
+		return "image/webp";
+	}
+	if contents[0..4] == b"8BPS"[..] && (contents[4..6] == [0, 1] || contents[4..6] == [0, 2]) {
+		/* Photoshop: version 1 is PSD, version 2 is PSB (large document) */
+		return "image/vnd.adobe.photoshop";
+	}
+	if contents.starts_with(EXIF_PREFIX) &&
+			(contents[6..8] == b"II"[..] || contents[6..8] == b"MM"[..]) {
+		/* bare EXIF payload, with the header of the JPEG APP1 segment */
+		return "application/x-exif";
+	}
+	if contents[0..4] == b"IIRO"[..] || contents[0..4] == b"MMOR"[..] ||
+			contents[0..4] == b"IIRS"[..] {
+		/* Olympus ORF, a TIFF variant */
+		return "image/x-olympus-orf";
+	}
+	if contents[0..4] == [b'I', b'I', 0x55, 0] {
+		/* Panasonic RW2, a TIFF variant */
+		return "image/x-panasonic-rw2";
+	}
+	if contents[0..4] == [b'I', b'I', 42, 0] && contents[8..11] == [b'C', b'R', 2] {
+		/* Canon CR2, a TIFF with a signature after the header */
+		return "image/x-canon-cr2";
 	}
 	if contents[0] == b'I' && contents[1] == b'I' &&
 			contents[2] == 42 && contents[3] == 0 {
@@ -31,56 +103,217 @@ pub fn detect_type(contents: &[u8]) -> &str
 		/* TIFF big-endian */
 		return "image/tiff";
 	}
+	if contents[0..4] == [b'I', b'I', 43, 0] || contents[0..4] == [b'M', b'M', 0, 43] {
+		/* BigTIFF, either endianness */
+		return "image/tiff";
+	}
 
 	return "";
 }
 
-/// Find the embedded TIFF in a JPEG image (that in turn contains the EXIF data)
-pub fn find_embedded_tiff_in_jpeg(contents: &[u8])
-								  -> Result<(usize, usize), ExifError>
+/// Tells if an IFD, or one of its SubIFDs, holds raw sensor data: a CFA
+/// PhotometricInterpretation, or the Compression of a vendor raw format
+/// (34713 Nikon NEF, 32767 Sony ARW, 65535 Pentax PEF)
+fn has_raw_data(d: &IfdDirectory) -> bool
 {
-	let mut offset = 2 as usize;
+	for entry in &d.entries {
+		let value = entry.value.to_i64(0);
+		match entry.ifd.tag {
+			/* PhotometricInterpretation */
+			0x0106 if value == Some(32803) => return true,
+			/* Compression */
+			0x0103 if value == Some(34713) || value == Some(32767) || value == Some(65535) => return true,
+			_ => {},
+		}
+	}
 
-	while offset < contents.len() {
-		if contents.len() < (offset + 4) {
-			return Err(ExifError::JpegWithoutExif("JPEG truncated in marker header".to_string()))
+	return d.children.iter().any(has_raw_data);
+}
+
+/// Tells apart the raw formats that are plain TIFF files, by the DNGVersion tag,
+/// or by the raw data in IFD0 or its SubIFDs and the camera maker. Returns the
+/// MIME type of the raw format, or "image/tiff" for other TIFF images, including
+/// the ones written by cameras that hold no raw data.
+pub fn classify_tiff(ifd0: &IfdDirectory) -> &'static str
+{
+	for entry in &ifd0.entries {
+		if entry.ifd.tag == 0xc612 {
+			/* DNGVersion */
+			return "image/x-adobe-dng";
 		}
+	}
+
+	if !has_raw_data(ifd0) {
+		return "image/tiff";
+	}
+
+	let make = ifd0.entries.iter()
+		.find(|e| e.tag == ExifTag::Make)
+		.map(|e| e.value_more_readable.to_uppercase())
+		.unwrap_or_default();
+
+	if make.starts_with("NIKON") {
+		return "image/x-nikon-nef";
+	} else if make.starts_with("SONY") {
+		return "image/x-sony-arw";
+	} else if make.starts_with("PENTAX") || make.starts_with("RICOH") {
+		return "image/x-pentax-pef";
+	}
+
+	return "image/tiff";
+}
 
-		let marker: u16 = u16::from(contents[offset]) * 256 + u16::from(contents[offset + 1]);
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testutil::*;
 
-		if marker < 0xff00 {
-			return Err(ExifError::JpegWithoutExif(format!("Invalid marker {:x}", marker)))
+	/// TIFF with the given Make, and raw data described by the entries of
+	/// IFD0 or of a SubIFD
+	fn camera_tiff(make: &str, ifd0: &[(u16, u64)], subifd: &[(u16, u64)]) -> Vec<u8>
+	{
+		let mut tiff = Tiff::new(true);
+		let mut entries: Vec<Entry> = vec![ascii(0x010f, make)];
+		entries.extend(ifd0.iter().map(|&(tag, value)| entry(tag, 3, &[value])));
+		if !subifd.is_empty() {
+			let sub: Vec<Entry> = subifd.iter().map(|&(tag, value)| entry(tag, 3, &[value])).collect();
+			let offset = tiff.ifd(&sub, 0);
+			entries.push(entry(0x014a, 4, &[offset]));
 		}
+		let ifd0 = tiff.ifd(&entries, 0);
+		return tiff.finish(ifd0);
+	}
+
+	fn mime(contents: &[u8]) -> String
+	{
+		return ::parse_buffer(contents).unwrap().mime;
+	}
 
-		offset += 2;
-		let size = (contents[offset] as usize) * 256 + (contents[offset + 1] as usize);
+	/// Pads a header to the minimum size that `detect_type` looks at
+	fn padded(header: &[u8]) -> Vec<u8>
+	{
+		let mut contents = header.to_vec();
+		contents.resize(header.len().max(16), 0);
+		return contents;
+	}
 
-		if size < 2 {
-			return Err(ExifError::JpegWithoutExif("JPEG marker size must be at least 2 (because of the size word)".to_string()))
+	#[test]
+	fn jpeg_detection() {
+		// whatever the first segment is
+		for first in [
+			&b"\xff\xe0\0\x10JFIF\0"[..],
+			b"\xff\xe1\0\x10Exif\0\0",
+			b"\xff\xe2\0\x10ICC_PROFILE\0",
+			b"\xff\xed\0\x10Photoshop 3.0\0",
+			b"\xff\xee\0\x0eAdobe\0",
+			b"\xff\xdb\0\x43\0",
+			b"\xff\xc0\0\x11\x08",
+			b"\xff\xfe\0\x05abc",
+			// fill bytes before the marker
+			b"\xff\xff\xff\xe1\0\x10Exif\0\0",
+		].iter() {
+			let mut contents = vec![0xff, 0xd8];
+			contents.extend_from_slice(first);
+			assert_eq!(detect_type(&padded(&contents)), "image/jpeg");
 		}
-		if contents.len() < (offset + size) {
-			return Err(ExifError::JpegWithoutExif("JPEG truncated in marker body".to_string()))
+
+		// no valid marker after SOI
+		for first in [
+			&b"\xff\xd0"[..],
+			b"\xff\xd7",
+			b"\xff\xff\xd3",
+			b"\xff\xd8",
+			b"\xff\xd9",
+			b"\xff\x01",
+			b"\xff\x00",
+			b"\x00\x10JFIF",
+			b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff",
+		].iter() {
+			let mut contents = vec![0xff, 0xd8];
+			contents.extend_from_slice(first);
+			contents.resize(16, 0xff);
+			assert_eq!(detect_type(&contents), "");
 		}
+		assert_eq!(detect_type(b"\xff\xd8\xff\xe1"), "");
+	}
 
-		if marker == 0xffe1 {
-			if size < 8 {
-				return Err(ExifError::JpegWithoutExif("EXIF preamble truncated".to_string()))
-			}
+	#[test]
+	fn exif_after_other_segments() {
+		let mut image = vec![0xff, 0xd8, 0xff, 0xdb, 0, 4, 0, 0, 0xff, 0xee, 0, 8];
+		image.extend_from_slice(b"Adobe\0");
+		let exif = jpeg_with_exif(&sample_exif(true));
+		// skips the SOI and the JFIF segment
+		image.extend_from_slice(&exif[20..]);
 
-			if contents[offset + 2 .. offset + 8]
-				!= [b'E', b'x', b'i', b'f', 0, 0] {
-				return Err(ExifError::JpegWithoutExif("EXIF preamble unrecognized".to_string()))
-			}
+		let d = ::parse_buffer(&image).unwrap();
+		assert_eq!(d.mime, "image/jpeg");
+		assert!(d.entries.iter().any(|e| e.tag == ExifTag::Make));
+	}
 
-			// The offset and size of the block, excluding size and 'Exif\0\0'.
-			return Ok((offset + 8, size - 8));
-		}
-		if marker == 0xffda {
-			// last marker
-			return Err(ExifError::JpegWithoutExif("Last mark found and no EXIF".to_string()))
+	#[test]
+	fn format_detection() {
+		for &(header, mime) in [
+			(&b"\x89PNG\r\n\x1a\n"[..], "image/png"),
+			(b"RIFF\0\0\0\0WEBPVP8X", "image/webp"),
+			(b"\0\0\0\x18ftypheic\0\0\0\0mif1", "image/heic"),
+			(b"\0\0\0\x18ftypmif1\0\0\0\0heic", "image/heic"),
+			(b"\0\0\0\x14ftypmif1\0\0\0\0", "image/heif"),
+			(b"\0\0\0\x18ftypavif\0\0\0\0mif1", "image/avif"),
+			(b"\0\0\0\x18ftypcrx \0\0\0\0isom", "image/x-canon-cr3"),
+			(b"\0\0\0\x14ftypisom\0\0\0\0", ""),
+			(b"\0\0\0\x0cJXL \r\n\x87\n", "image/jxl"),
+			(b"\xff\x0a\xfa\x1f", "image/jxl"),
+			(b"FUJIFILMCCD-RAW 0201", "image/x-fuji-raf"),
+			(b"IIRO\x08\0\0\0", "image/x-olympus-orf"),
+			(b"MMOR\0\0\0\x08", "image/x-olympus-orf"),
+			(b"IIU\0\x18\0\0\0", "image/x-panasonic-rw2"),
+			(b"II*\0\x10\0\0\0CR\x02\0", "image/x-canon-cr2"),
+			(b"II*\0\x08\0\0\0", "image/tiff"),
+			(b"MM\0*\0\0\0\x08", "image/tiff"),
+			(b"II+\0\x08\0\0\0\x10\0\0\0", "image/tiff"),
+			(b"MM\0+\0\x08\0\0", "image/tiff"),
+			(b"Exif\0\0II*\0\x08\0\0\0", "application/x-exif"),
+			(b"Exif\0\0MM\0*\0\0\0\x08", "application/x-exif"),
+			(b"Exif\0\0GIF89a", ""),
+			(b"8BPS\0\x01\0\0\0\0\0\0", "image/vnd.adobe.photoshop"),
+			(b"8BPS\0\x02\0\0\0\0\0\0", "image/vnd.adobe.photoshop"),
+			(b"8BPS\0\x03\0\0\0\0\0\0", ""),
+			(b"GIF89a", ""),
+		].iter() {
+			assert_eq!(detect_type(&padded(header)), mime, "{:?}", header);
 		}
-		offset += size;
+		assert_eq!(detect_type(b"II*\0"), "");
+	}
+
+	#[test]
+	fn raw_tiff() {
+		// vendor compression of the raw data in a SubIFD, or in IFD0
+		assert_eq!(mime(&camera_tiff("NIKON CORPORATION", &[(0x0103, 6)], &[(0x0103, 34713)])), "image/x-nikon-nef");
+		assert_eq!(mime(&camera_tiff("SONY", &[(0x0103, 32767)], &[])), "image/x-sony-arw");
+		assert_eq!(mime(&camera_tiff("PENTAX", &[(0x0103, 65535)], &[])), "image/x-pentax-pef");
+		assert_eq!(mime(&camera_tiff("RICOH IMAGING COMPANY, LTD.", &[(0x0103, 65535)], &[])), "image/x-pentax-pef");
+		// uncompressed CFA data
+		assert_eq!(mime(&camera_tiff("NIKON", &[], &[(0x0103, 1), (0x0106, 32803)])), "image/x-nikon-nef");
+		assert_eq!(mime(&camera_tiff("SONY", &[(0x0103, 1), (0x0106, 32803)], &[])), "image/x-sony-arw");
+
+		let mut tiff = Tiff::new(false);
+		let ifd0 = tiff.ifd(&[ascii(0x010f, "Canon"), entry(0xc612, 1, &[1, 4, 0, 0])], 0);
+		assert_eq!(mime(&tiff.finish(ifd0)), "image/x-adobe-dng");
 	}
 
-	return Err(ExifError::JpegWithoutExif("Scan past EOF and no EXIF found".to_string()))
+	#[test]
+	fn plain_tiff() {
+		// written by a camera maker, but holding no raw data, e.g. a scan or
+		// an edited image
+		assert_eq!(mime(&camera_tiff("NIKON", &[(0x0103, 1), (0x0106, 2)], &[])), "image/tiff");
+		assert_eq!(mime(&camera_tiff("SONY", &[(0x0103, 5)], &[(0x0103, 7), (0x0106, 6)])), "image/tiff");
+		assert_eq!(mime(&camera_tiff("PENTAX", &[], &[])), "image/tiff");
+		// raw data of an unknown maker
+		assert_eq!(mime(&camera_tiff("Acme", &[(0x0103, 34713)], &[(0x0106, 32803)])), "image/tiff");
+		// vendor compression in an IFD that is not a SubIFD of IFD0
+		let mut tiff = Tiff::new(true);
+		let ifd1 = tiff.ifd(&[entry(0x0103, 3, &[34713])], 0);
+		let ifd0 = tiff.ifd(&[ascii(0x010f, "NIKON")], ifd1);
+		assert_eq!(mime(&tiff.finish(ifd0)), "image/tiff");
+	}
 }
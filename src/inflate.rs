@@ -0,0 +1,358 @@
+use super::lowlevel::{slice_at, read_u32};
+
+/// Reads a DEFLATE stream bit by bit, least significant bit first
+struct BitReader<'a> {
+	data: &'a [u8],
+	pos: usize,
+	buffer: u32,
+	count: u32,
+}
+
+impl<'a> BitReader<'a> {
+	fn new(data: &'a [u8]) -> BitReader<'a>
+	{
+		BitReader { data, pos: 0, buffer: 0, count: 0 }
+	}
+
+	/// Gets the next `n` bits (up to 16), or None at the end of data
+	fn bits(&mut self, n: u32) -> Option<u32>
+	{
+		while self.count < n {
+			let byte = *self.data.get(self.pos)?;
+			self.pos += 1;
+			self.buffer |= u32::from(byte) << self.count;
+			self.count += 8;
+		}
+		let value = self.buffer & ((1 << n) - 1);
+		self.buffer >>= n;
+		self.count -= n;
+		return Some(value);
+	}
+
+	/// Discards the remaining bits of the current byte
+	fn align(&mut self)
+	{
+		self.buffer = 0;
+		self.count = 0;
+	}
+}
+
+/// Canonical Huffman code, described by the count of codes of each length
+/// and the symbols ordered by code
+struct Huffman {
+	counts: [u16; 16],
+	symbols: Vec<u16>,
+}
+
+impl Huffman {
+	/// Builds the code from the code length of each symbol (0 = unused).
+	/// Returns None if the lengths are over-subscribed.
+	fn new(lengths: &[u8]) -> Option<Huffman>
+	{
+		let mut counts = [0u16; 16];
+		for &length in lengths {
+			counts[length as usize] += 1;
+		}
+
+		let mut left: i32 = 1;
+		for &count in &counts[1..] {
+			left <<= 1;
+			left -= i32::from(count);
+			if left < 0 {
+				return None;
+			}
+		}
+
+		let mut offsets = [0u16; 16];
+		for i in 1..15 {
+			offsets[i + 1] = offsets[i] + counts[i];
+		}
+
+		let mut symbols = vec![0u16; lengths.len()];
+		for (symbol, &length) in lengths.iter().enumerate() {
+			if length != 0 {
+				symbols[offsets[length as usize] as usize] = symbol as u16;
+				offsets[length as usize] += 1;
+			}
+		}
+
+		return Some(Huffman { counts, symbols });
+	}
+
+	/// Decodes the next symbol
+	fn decode(&self, reader: &mut BitReader) -> Option<u16>
+	{
+		let mut code: i32 = 0;
+		let mut first: i32 = 0;
+		let mut index: i32 = 0;
+
+		for &count in &self.counts[1..] {
+			code |= reader.bits(1)? as i32;
+			let count = i32::from(count);
+			if code - first < count {
+				return self.symbols.get((index + code - first) as usize).cloned();
+			}
+			index += count;
+			first += count;
+			first <<= 1;
+			code <<= 1;
+		}
+
+		return None;
+	}
+}
+
+static LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+	35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+static LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+	3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+static DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+	257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+static DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+	7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Order in which the code lengths of the code length alphabet are stored
+static CLEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Decodes the symbols of a compressed block until the end-of-block symbol
+fn inflate_codes(reader: &mut BitReader, out: &mut Vec<u8>, max_size: usize,
+			lencode: &Huffman, distcode: &Huffman) -> Option<()>
+{
+	loop {
+		let symbol = lencode.decode(reader)? as usize;
+		if symbol < 256 {
+			out.push(symbol as u8);
+		} else if symbol == 256 {
+			return Some(());
+		} else {
+			let symbol = symbol - 257;
+			if symbol >= 29 {
+				return None;
+			}
+			let length = LENGTH_BASE[symbol] as usize +
+				reader.bits(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+
+			let symbol = distcode.decode(reader)? as usize;
+			if symbol >= 30 {
+				return None;
+			}
+			let dist = DIST_BASE[symbol] as usize +
+				reader.bits(u32::from(DIST_EXTRA[symbol]))? as usize;
+
+			if dist > out.len() {
+				return None;
+			}
+			for _ in 0..length {
+				let byte = out[out.len() - dist];
+				out.push(byte);
+			}
+		}
+
+		if out.len() > max_size {
+			return None;
+		}
+	}
+}
+
+/// Reads the code lengths of a block with dynamic Huffman codes
+fn dynamic_codes(reader: &mut BitReader) -> Option<(Huffman, Huffman)>
+{
+	let nlen = reader.bits(5)? as usize + 257;
+	let ndist = reader.bits(5)? as usize + 1;
+	let ncode = reader.bits(4)? as usize + 4;
+	if nlen > 286 || ndist > 30 {
+		return None;
+	}
+
+	let mut clengths = [0u8; 19];
+	for &i in &CLEN_ORDER[..ncode] {
+		clengths[i] = reader.bits(3)? as u8;
+	}
+	let clencode = Huffman::new(&clengths)?;
+
+	let mut lengths: Vec<u8> = Vec::new();
+	while lengths.len() < nlen + ndist {
+		let symbol = clencode.decode(reader)?;
+		let (value, repeat) = match symbol {
+			0..=15 => (symbol as u8, 1),
+			16 => (*lengths.last()?, 3 + reader.bits(2)?),
+			17 => (0, 3 + reader.bits(3)?),
+			_ => (0, 11 + reader.bits(7)?),
+		};
+		for _ in 0..repeat {
+			lengths.push(value);
+		}
+	}
+	if lengths.len() > nlen + ndist || lengths[256] == 0 {
+		return None;
+	}
+
+	let lencode = Huffman::new(&lengths[..nlen])?;
+	let distcode = Huffman::new(&lengths[nlen..])?;
+	return Some((lencode, distcode));
+}
+
+/// Decompresses a raw DEFLATE stream (RFC 1951). Returns the data and the size
+/// of the stream, rounded up to a whole byte, where whatever follows it begins.
+/// Returns None if the stream is broken, or if the output would be larger than
+/// `max_size`.
+pub fn inflate(data: &[u8], max_size: usize) -> Option<(Vec<u8>, usize)>
+{
+	let mut reader = BitReader::new(data);
+	let mut out: Vec<u8> = Vec::new();
+
+	loop {
+		let last = reader.bits(1)?;
+		match reader.bits(2)? {
+			0 => {
+				// stored block
+				reader.align();
+				let header = slice_at(data, reader.pos, 4)?;
+				let length = usize::from(header[0]) + usize::from(header[1]) * 256;
+				let nlength = usize::from(header[2]) + usize::from(header[3]) * 256;
+				if length != (!nlength & 0xffff) {
+					return None;
+				}
+				out.extend(slice_at(data, reader.pos + 4, length)?);
+				reader.pos += 4 + length;
+			},
+			1 => {
+				let mut lengths = [0u8; 288 + 30];
+				for (i, length) in lengths.iter_mut().enumerate() {
+					*length = match i {
+						0..=143 => 8,
+						144..=255 => 9,
+						256..=279 => 7,
+						280..=287 => 8,
+						_ => 5,
+					};
+				}
+				let lencode = Huffman::new(&lengths[..288])?;
+				let distcode = Huffman::new(&lengths[288..])?;
+				inflate_codes(&mut reader, &mut out, max_size, &lencode, &distcode)?;
+			},
+			2 => {
+				let (lencode, distcode) = dynamic_codes(&mut reader)?;
+				inflate_codes(&mut reader, &mut out, max_size, &lencode, &distcode)?;
+			},
+			_ => return None,
+		}
+
+		if out.len() > max_size {
+			return None;
+		}
+		if last != 0 {
+			reader.align();
+			return Some((out, reader.pos));
+		}
+	}
+}
+
+/// Computes the Adler-32 checksum of a buffer (RFC 1950)
+fn adler32(data: &[u8]) -> u32
+{
+	let (mut a, mut b) = (1u32, 0u32);
+	// 5552 is the largest block whose sums can not overflow before the modulo
+	for block in data.chunks(5552) {
+		for &byte in block {
+			a += u32::from(byte);
+			b += a;
+		}
+		a %= 65521;
+		b %= 65521;
+	}
+	return (b << 16) | a;
+}
+
+/// Decompresses a zlib stream (RFC 1950), i.e. a DEFLATE stream with a
+/// two-byte header and an Adler-32 checksum of the decompressed data.
+/// Preset dictionaries are not supported. Returns None if the checksum
+/// does not match.
+pub fn zlib_decompress(data: &[u8], max_size: usize) -> Option<Vec<u8>>
+{
+	if data.len() < 2 {
+		return None;
+	}
+	let (cmf, flg) = (data[0], data[1]);
+	if cmf & 0x0f != 8 || (u16::from(cmf) * 256 + u16::from(flg)) % 31 != 0 || flg & 0x20 != 0 {
+		return None;
+	}
+
+	let (out, size) = inflate(&data[2..], max_size)?;
+	let checksum = slice_at(data, 2 + size, 4)?;
+	if read_u32(false, checksum) != adler32(&out) {
+		return None;
+	}
+	return Some(out);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn hex(s: &str) -> Vec<u8>
+	{
+		(0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+	}
+
+	const TEXT: &[u8] = b"Hello, hello, hello! Stored, fixed and dynamic blocks.";
+
+	#[test]
+	fn stored_block()
+	{
+		let data = hex("7801013600c9ff48656c6c6f2c2068656c6c6f2c2068656c6c6f212053746f7265642c20\
+			666978656420616e642064796e616d696320626c6f636b732e005a12e7");
+		assert_eq!(zlib_decompress(&data, 1000).unwrap(), TEXT);
+	}
+
+	#[test]
+	fn fixed_block()
+	{
+		let data = hex("78daf348cdc9c9d751c840a21415824bf28b52537414d2322b52531412f35214522af3\
+			12733393159272f293b38bf500005a12e7");
+		assert_eq!(zlib_decompress(&data, 1000).unwrap(), TEXT);
+	}
+
+	#[test]
+	fn dynamic_block()
+	{
+		let text: String = (0..40).map(|i| format!("{} tree, {} bush; ", i * 7 % 13, i * i % 17)).collect();
+		let data = hex("78da8591b10dc3300c0457d100294859a624649b00065c27f6fe699e29ce3052de8322\
+			799495e3bd6d8f62e5757ef667e96217bbb88987788a6bd68782a960881771cd86962f56\
+			252d83254b9cc9ca375ed13630d6b857c7e277620e318397c3abd16b522ba835605529f5\
+			d30c1ec218f4cb65d07460e8ed87cd7f620e31bb7c17bc1abd26b5025ab9e4178f84bfe3");
+		assert_eq!(data[2] >> 1 & 3, 2);
+		assert_eq!(zlib_decompress(&data, 1000).unwrap(), text.as_bytes());
+		// output limit
+		assert_eq!(zlib_decompress(&data, 100), None);
+	}
+
+	#[test]
+	fn truncated_stream()
+	{
+		let data = hex("78daf348cdc9c9d751c840a21415824bf28b52537414d2322b52531412f35214522af3\
+			12733393159272f293b38bf500005a12e7");
+		for size in 0..data.len() {
+			assert_eq!(zlib_decompress(&data[..size], 1000), None);
+		}
+	}
+
+	#[test]
+	fn bad_checksum()
+	{
+		let mut data = hex("78daf348cdc9c9d751c840a21415824bf28b52537414d2322b52531412f35214522af3\
+			12733393159272f293b38bf500005a12e7");
+		let last = data.len() - 1;
+		data[last] ^= 1;
+		assert_eq!(zlib_decompress(&data, 1000), None);
+		// raw DEFLATE has no checksum
+		assert_eq!(inflate(&data[2..], 1000).unwrap(), (TEXT.to_vec(), data.len() - 6));
+	}
+
+	#[test]
+	fn adler32_known_answer()
+	{
+		assert_eq!(adler32(b""), 1);
+		assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+	}
+}
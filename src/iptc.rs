@@ -0,0 +1,308 @@
+use super::types::*;
+use super::lowlevel::*;
+use super::source::ByteSource;
+use super::irb::*;
+use super::jpeg::find_photoshop_irb_in_jpeg;
+use super::psd::find_photoshop_irb_in_psd;
+
+/// Image resource that holds the IPTC-NAA record
+const IPTC_RESOURCE: u16 = 0x0404;
+
+/// TIFF tag of the IPTC-NAA record
+const IPTC_TAG: u16 = 0x83bb;
+
+/// Value of dataset 1:90 (CodedCharacterSet) that declares UTF-8 text
+const UTF8_ESCAPE: &[u8] = b"\x1b%G";
+
+/// How the data of a dataset is interpreted
+#[derive(Copy, Clone, PartialEq)]
+enum DatasetFormat {
+	Text,
+	/// Unsigned 16-bit integer
+	Short,
+	Binary,
+}
+
+/// Known datasets of IPTC-IIM 4.2: record, dataset, name and format.
+/// Datasets not listed are taken as text.
+static DATASETS: [(u8, u8, &str, DatasetFormat); 79] = [
+	(1, 0, "EnvelopeRecordVersion", DatasetFormat::Short),
+	(1, 5, "Destination", DatasetFormat::Text),
+	(1, 20, "FileFormat", DatasetFormat::Short),
+	(1, 22, "FileVersion", DatasetFormat::Short),
+	(1, 30, "ServiceIdentifier", DatasetFormat::Text),
+	(1, 40, "EnvelopeNumber", DatasetFormat::Text),
+	(1, 50, "ProductID", DatasetFormat::Text),
+	(1, 60, "EnvelopePriority", DatasetFormat::Text),
+	(1, 70, "DateSent", DatasetFormat::Text),
+	(1, 80, "TimeSent", DatasetFormat::Text),
+	(1, 90, "CodedCharacterSet", DatasetFormat::Binary),
+	(1, 100, "UniqueObjectName", DatasetFormat::Text),
+	(1, 120, "ARMIdentifier", DatasetFormat::Short),
+	(1, 122, "ARMVersion", DatasetFormat::Short),
+	(2, 0, "ApplicationRecordVersion", DatasetFormat::Short),
+	(2, 3, "ObjectTypeReference", DatasetFormat::Text),
+	(2, 4, "ObjectAttributeReference", DatasetFormat::Text),
+	(2, 5, "ObjectName", DatasetFormat::Text),
+	(2, 7, "EditStatus", DatasetFormat::Text),
+	(2, 8, "EditorialUpdate", DatasetFormat::Text),
+	(2, 10, "Urgency", DatasetFormat::Text),
+	(2, 12, "SubjectReference", DatasetFormat::Text),
+	(2, 15, "Category", DatasetFormat::Text),
+	(2, 20, "SupplementalCategories", DatasetFormat::Text),
+	(2, 22, "FixtureIdentifier", DatasetFormat::Text),
+	(2, 25, "Keywords", DatasetFormat::Text),
+	(2, 26, "ContentLocationCode", DatasetFormat::Text),
+	(2, 27, "ContentLocationName", DatasetFormat::Text),
+	(2, 30, "ReleaseDate", DatasetFormat::Text),
+	(2, 35, "ReleaseTime", DatasetFormat::Text),
+	(2, 37, "ExpirationDate", DatasetFormat::Text),
+	(2, 38, "ExpirationTime", DatasetFormat::Text),
+	(2, 40, "SpecialInstructions", DatasetFormat::Text),
+	(2, 42, "ActionAdvised", DatasetFormat::Text),
+	(2, 45, "ReferenceService", DatasetFormat::Text),
+	(2, 47, "ReferenceDate", DatasetFormat::Text),
+	(2, 50, "ReferenceNumber", DatasetFormat::Text),
+	(2, 55, "DateCreated", DatasetFormat::Text),
+	(2, 60, "TimeCreated", DatasetFormat::Text),
+	(2, 62, "DigitalCreationDate", DatasetFormat::Text),
+	(2, 63, "DigitalCreationTime", DatasetFormat::Text),
+	(2, 65, "OriginatingProgram", DatasetFormat::Text),
+	(2, 70, "ProgramVersion", DatasetFormat::Text),
+	(2, 75, "ObjectCycle", DatasetFormat::Text),
+	(2, 80, "By-line", DatasetFormat::Text),
+	(2, 85, "By-lineTitle", DatasetFormat::Text),
+	(2, 90, "City", DatasetFormat::Text),
+	(2, 92, "Sub-location", DatasetFormat::Text),
+	(2, 95, "Province-State", DatasetFormat::Text),
+	(2, 100, "Country-PrimaryLocationCode", DatasetFormat::Text),
+	(2, 101, "Country-PrimaryLocationName", DatasetFormat::Text),
+	(2, 103, "OriginalTransmissionReference", DatasetFormat::Text),
+	(2, 105, "Headline", DatasetFormat::Text),
+	(2, 110, "Credit", DatasetFormat::Text),
+	(2, 115, "Source", DatasetFormat::Text),
+	(2, 116, "CopyrightNotice", DatasetFormat::Text),
+	(2, 118, "Contact", DatasetFormat::Text),
+	(2, 120, "Caption-Abstract", DatasetFormat::Text),
+	(2, 121, "LocalCaption", DatasetFormat::Text),
+	(2, 122, "Writer-Editor", DatasetFormat::Text),
+	(2, 125, "RasterizedCaption", DatasetFormat::Binary),
+	(2, 130, "ImageType", DatasetFormat::Text),
+	(2, 131, "ImageOrientation", DatasetFormat::Text),
+	(2, 135, "LanguageIdentifier", DatasetFormat::Text),
+	(2, 150, "AudioType", DatasetFormat::Text),
+	(2, 151, "AudioSamplingRate", DatasetFormat::Text),
+	(2, 152, "AudioSamplingResolution", DatasetFormat::Text),
+	(2, 153, "AudioDuration", DatasetFormat::Text),
+	(2, 154, "AudioOutcue", DatasetFormat::Text),
+	(2, 184, "JobID", DatasetFormat::Text),
+	(2, 185, "MasterDocumentID", DatasetFormat::Text),
+	(2, 186, "ShortDocumentID", DatasetFormat::Text),
+	(2, 187, "UniqueDocumentID", DatasetFormat::Text),
+	(2, 188, "OwnerID", DatasetFormat::Text),
+	(2, 200, "ObjectPreviewFileFormat", DatasetFormat::Short),
+	(2, 201, "ObjectPreviewFileVersion", DatasetFormat::Short),
+	(2, 202, "ObjectPreviewData", DatasetFormat::Binary),
+	(2, 221, "Prefs", DatasetFormat::Text),
+	(2, 225, "ClassifyState", DatasetFormat::Text),
+];
+
+/// Decodes text as UTF-8 if declared so, or if it is valid UTF-8 anyway.
+/// Otherwise, it is taken as ISO 8859-1, the most common legacy character set.
+fn decode_text(data: &[u8], utf8: bool) -> String
+{
+	if utf8 {
+		return String::from_utf8_lossy(data).into_owned();
+	}
+	match String::from_utf8(data.to_vec()) {
+		Ok(text) => text,
+		Err(_) => data.iter().map(|&c| char::from(c)).collect(),
+	}
+}
+
+/// Readable value of a dataset
+fn dataset_value(data: &[u8], format: DatasetFormat, utf8: bool) -> String
+{
+	match format {
+		DatasetFormat::Short if data.len() == 2 => format!("{}", read_u16(false, data)),
+		DatasetFormat::Text => decode_text(data, utf8),
+		_ => format!("<{} bytes>", data.len()),
+	}
+}
+
+/// Parses an IPTC-IIM block, a sequence of datasets. Each dataset starts with
+/// the tag marker 0x1C, the record and dataset numbers, and the data length.
+/// Parsing ends at the first byte that is not a tag marker, normally padding.
+///
+/// Text is decoded as UTF-8 if dataset 1:90 (CodedCharacterSet) says so.
+/// Repeatable datasets, e.g. 2:25 (Keywords), are listed once per value.
+pub fn parse_iptc(data: &[u8]) -> Result<Vec<IptcDataset>, ExifError>
+{
+	let mut raw: Vec<(u8, u8, &[u8])> = Vec::new();
+	let mut offset: usize = 0;
+
+	while data.get(offset) == Some(&0x1c) {
+		let header = slice_at(data, offset, 5)
+			.ok_or_else(|| ExifError::IptcMalformed("Truncated dataset header".to_string()))?;
+		offset += 5;
+
+		let mut length = read_u16(false, &header[3..5]) as usize;
+		if length & 0x8000 != 0 {
+			// extended dataset: the low bits tell the size of the length field
+			let size = length & 0x7fff;
+			let raw_length = slice_at(data, offset, size).filter(|_| size <= 4)
+				.ok_or_else(|| ExifError::IptcMalformed("Bad extended dataset length".to_string()))?;
+			length = raw_length.iter().fold(0usize, |n, &c| (n << 8) | c as usize);
+			offset += size;
+		}
+
+		let contents = slice_at(data, offset, length)
+			.ok_or_else(|| ExifError::IptcMalformed(format!("Dataset {}:{} truncated", header[1], header[2])))?;
+		offset += length;
+
+		raw.push((header[1], header[2], contents));
+	}
+
+	let utf8 = raw.iter().any(|&(record, dataset, contents)| record == 1 && dataset == 90 && contents == UTF8_ESCAPE);
+
+	let datasets = raw.into_iter().map(|(record, dataset, contents)| {
+		let (name, format) = DATASETS.iter()
+			.find(|d| d.0 == record && d.1 == dataset)
+			.map(|d| (d.2, d.3))
+			.unwrap_or(("", DatasetFormat::Text));
+		IptcDataset {
+			record,
+			dataset,
+			name: name.to_string(),
+			data: contents.to_vec(),
+			value: dataset_value(contents, format, utf8),
+		}
+	}).collect();
+
+	return Ok(datasets);
+}
+
+/// Parses the IPTC-NAA resource of a Photoshop Image Resource Block
+fn iptc_from_irb(irb: &[u8]) -> Option<Vec<IptcDataset>>
+{
+	// resources are not decoded, as the EXIF ones would recurse into parse_tiff
+	let resources = split_image_resources(irb).ok()?;
+	return parse_iptc(find_image_resource(&resources, IPTC_RESOURCE)?).ok();
+}
+
+/// Parses the IPTC-NAA record of TIFF tag 33723, or else the one within the
+/// Photoshop Image Resource Block of TIFF tag 34377, in IFD0. Returns no
+/// datasets if there is no record, or if it is broken.
+pub fn iptc_from_entries(entries: &[ExifEntry]) -> Vec<IptcDataset>
+{
+	let tag_data = |tag: u16| entries.iter()
+		.find(|e| e.ifd_kind == IfdKind::Ifd0 && e.ifd.tag == tag)
+		.map(|e| &e.ifd.data[..]);
+
+	tag_data(IPTC_TAG).and_then(|data| parse_iptc(data).ok())
+		.or_else(|| tag_data(PHOTOSHOP_TAG).and_then(iptc_from_irb))
+		.unwrap_or_default()
+}
+
+/// Finds the IPTC-NAA record that an image of a given type stores outside of
+/// the TIFF block (JPEG APP13 segments, PSD image resources), and parses it. Returns None if there
+/// is no record, or if it is broken.
+pub fn read_container_iptc<S: ByteSource>(mime: &str, contents: &mut S) -> Option<Vec<IptcDataset>>
+{
+	let irb = match mime {
+		"image/jpeg" => find_photoshop_irb_in_jpeg(contents),
+		"image/vnd.adobe.photoshop" => find_photoshop_irb_in_psd(contents).ok(),
+		_ => None,
+	};
+
+	return iptc_from_irb(&irb?);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Dataset with a standard (short) length
+	fn dataset(record: u8, dataset: u8, data: &[u8]) -> Vec<u8>
+	{
+		let mut raw = vec![0x1c, record, dataset];
+		raw.extend_from_slice(&(data.len() as u16).to_be_bytes());
+		raw.extend_from_slice(data);
+		return raw;
+	}
+
+	fn values(datasets: &[IptcDataset]) -> Vec<(u8, u8, &str, &str)>
+	{
+		datasets.iter().map(|d| (d.record, d.dataset, &d.name[..], &d.value[..])).collect()
+	}
+
+	#[test]
+	fn datasets() {
+		let mut data = dataset(2, 0, &[0, 4]);
+		data.extend(dataset(2, 25, b"beach"));
+		data.extend(dataset(2, 25, b"sun"));
+		data.extend(dataset(2, 90, b"Paris"));
+		data.extend(dataset(2, 202, &[1, 2, 3]));
+		data.extend(dataset(2, 250, b"unknown"));
+
+		let datasets = parse_iptc(&data).unwrap();
+		assert_eq!(values(&datasets), vec![
+			(2, 0, "ApplicationRecordVersion", "4"),
+			(2, 25, "Keywords", "beach"),
+			(2, 25, "Keywords", "sun"),
+			(2, 90, "City", "Paris"),
+			(2, 202, "ObjectPreviewData", "<3 bytes>"),
+			(2, 250, "", "unknown"),
+		]);
+		assert_eq!(datasets[4].data, vec![1, 2, 3]);
+	}
+
+	#[test]
+	fn character_sets() {
+		// ISO 8859-1 unless declared UTF-8, or valid UTF-8 anyway
+		let latin1 = dataset(2, 90, b"Z\xfcrich");
+		assert_eq!(parse_iptc(&latin1).unwrap()[0].value, "Z\u{fc}rich");
+
+		let utf8 = dataset(2, 90, "Z\u{fc}rich".as_bytes());
+		assert_eq!(parse_iptc(&utf8).unwrap()[0].value, "Z\u{fc}rich");
+
+		// declared UTF-8 wins, even over invalid sequences
+		let mut declared = dataset(1, 90, UTF8_ESCAPE);
+		declared.extend(dataset(2, 90, b"Z\xfcrich"));
+		let datasets = parse_iptc(&declared).unwrap();
+		assert_eq!(values(&datasets)[0], (1, 90, "CodedCharacterSet", "<3 bytes>"));
+		assert_eq!(datasets[1].value, "Z\u{fffd}rich");
+	}
+
+	#[test]
+	fn extended_length() {
+		// length field of 4 bytes, after the 0x8004 marker
+		let mut data = vec![0x1c, 2, 120, 0x80, 0x04, 0, 0, 0, 5];
+		data.extend_from_slice(b"Hello");
+		data.extend(dataset(2, 5, b"Title"));
+
+		assert_eq!(values(&parse_iptc(&data).unwrap()), vec![
+			(2, 120, "Caption-Abstract", "Hello"),
+			(2, 5, "ObjectName", "Title"),
+		]);
+
+		assert!(parse_iptc(&[0x1c, 2, 120, 0x80, 0x05, 0, 0, 0, 0, 5]).is_err());
+		assert!(parse_iptc(&[0x1c, 2, 120, 0x80, 0x04, 0, 0]).is_err());
+	}
+
+	#[test]
+	fn end_of_record() {
+		// parsing stops at padding
+		let mut data = dataset(2, 5, b"Title");
+		data.extend_from_slice(&[0, 0, 0x1c]);
+		assert_eq!(parse_iptc(&data).unwrap().len(), 1);
+		assert!(parse_iptc(&[]).unwrap().is_empty());
+	}
+
+	#[test]
+	fn truncated() {
+		let data = dataset(2, 5, b"Title");
+		assert!(parse_iptc(&data[..data.len() - 1]).is_err());
+		assert!(parse_iptc(&data[..4]).is_err());
+	}
+}
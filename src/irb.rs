@@ -0,0 +1,267 @@
+use super::types::*;
+use super::lowlevel::*;
+use super::image::detect_type;
+use super::tiff::parse_tiff;
+use super::iptc::parse_iptc;
+use super::xmp::parse_xmp;
+use super::jpeg::find_photoshop_irb_in_jpeg;
+use super::psd::find_photoshop_irb_in_psd;
+use super::find_tiff_block;
+
+/// TIFF tag of the Photoshop Image Resource Block
+pub const PHOTOSHOP_TAG: u16 = 0x8649;
+
+/// Signatures of image resources. "8BIM" is by far the most common one.
+static SIGNATURES: [&[u8]; 5] = [b"8BIM", b"PHUT", b"AgHg", b"DCSR", b"MeSa"];
+
+/// Size of the header of thumbnail resources, before the image itself
+const THUMBNAIL_HEADER_SIZE: usize = 28;
+
+/// Splits an Image Resource Block into its resources, without decoding them.
+/// Each one has a signature, an ID, a name (Pascal string padded to even size),
+/// the data size and the data (padded to even size). The listing ends at the
+/// first unknown signature, normally padding.
+pub fn split_image_resources(data: &[u8]) -> Result<Vec<ImageResource>, ExifError>
+{
+	let mut resources: Vec<ImageResource> = Vec::new();
+	let mut offset: usize = 0;
+
+	while let Some(header) = slice_at(data, offset, 7) {
+		if !SIGNATURES.iter().any(|s| header[0..4] == **s) {
+			break;
+		}
+		let id = read_u16(false, &header[4..6]);
+
+		let name_length = header[6] as usize;
+		let name = slice_at(data, offset + 7, name_length)
+			.map(|name| name.iter().map(|&c| char::from(c)).collect())
+			.ok_or_else(|| ExifError::IrbMalformed(format!("Resource {:04x} name truncated", id)))?;
+		// length byte and name are padded to an even size
+		offset += 6 + ((name_length + 2) & !1);
+
+		let size = slice_at(data, offset, 4)
+			.map(|size| read_u32(false, size) as usize)
+			.ok_or_else(|| ExifError::IrbMalformed(format!("Resource {:04x} size truncated", id)))?;
+		offset += 4;
+
+		let contents = slice_at(data, offset, size)
+			.ok_or_else(|| ExifError::IrbMalformed(format!("Resource {:04x} truncated", id)))?
+			.to_vec();
+		offset += size + (size & 1);
+
+		resources.push(ImageResource {
+			id,
+			name,
+			data: contents,
+			value: ImageResourceValue::Raw,
+		});
+	}
+
+	return Ok(resources);
+}
+
+/// Gets the data of the first resource with a given ID
+pub fn find_image_resource(resources: &[ImageResource], id: u16) -> Option<&[u8]>
+{
+	resources.iter().find(|r| r.id == id).map(|r| &r.data[..])
+}
+
+/// Decodes the resources whose format is known. Resources that fail to decode
+/// are kept raw.
+fn decode_image_resource(id: u16, data: &[u8]) -> ImageResourceValue
+{
+	match id {
+		0x03ed if data.len() >= 16 => {
+			// horizontal and vertical resolution, as 16.16 fixed point
+			let fixed = |raw: &[u8]| read_u32(false, raw) as f64 / 65536.0;
+			ImageResourceValue::ResolutionInfo {
+				x_resolution: fixed(&data[0..4]),
+				x_resolution_unit: read_u16(false, &data[4..6]),
+				width_unit: read_u16(false, &data[6..8]),
+				y_resolution: fixed(&data[8..12]),
+				y_resolution_unit: read_u16(false, &data[12..14]),
+				height_unit: read_u16(false, &data[14..16]),
+			}
+		},
+		0x0409 | 0x040c if data.len() >= THUMBNAIL_HEADER_SIZE => {
+			ImageResourceValue::Thumbnail {
+				format: read_u32(false, &data[0..4]),
+				width: read_u32(false, &data[4..8]),
+				height: read_u32(false, &data[8..12]),
+				data: data[THUMBNAIL_HEADER_SIZE..].to_vec(),
+			}
+		},
+		0x0404 => match parse_iptc(data) {
+			Ok(datasets) => ImageResourceValue::Iptc(datasets),
+			Err(_) => ImageResourceValue::Raw,
+		},
+		0x0422 | 0x0423 => match parse_tiff(data) {
+			Ok(exif) => ImageResourceValue::Exif(Box::new(exif)),
+			Err(_) => ImageResourceValue::Raw,
+		},
+		0x0424 => match parse_xmp(data) {
+			Ok(properties) => ImageResourceValue::Xmp(properties),
+			Err(_) => ImageResourceValue::Raw,
+		},
+		_ => ImageResourceValue::Raw,
+	}
+}
+
+/// Parses a Photoshop Image Resource Block, as found in JPEG APP13 segments
+/// (after the "Photoshop 3.0" signature), TIFF tag 34377 and PSD files.
+/// Returns every resource in file order, with typed values for the common ones:
+/// resolution (0x03ED), IPTC-NAA (0x0404), thumbnail (0x0409, 0x040C),
+/// EXIF (0x0422, 0x0423) and XMP (0x0424).
+pub fn parse_image_resources(data: &[u8]) -> Result<Vec<ImageResource>, ExifError>
+{
+	let mut resources = split_image_resources(data)?;
+	for resource in &mut resources {
+		resource.value = decode_image_resource(resource.id, &resource.data);
+	}
+	return Ok(resources);
+}
+
+/// Finds the Photoshop Image Resource Block of a JPEG (APP13 segments), PSD/PSB
+/// (Image Resources section) or other image (tag 34377 in the IFD0 of the TIFF
+/// block, e.g. TIFF and TIFF-based raw images), and parses it, see
+/// `parse_image_resources`.
+/// Returns no resources if the image has no such block.
+pub fn read_image_resources(contents: &[u8]) -> Result<Vec<ImageResource>, ExifError>
+{
+	let irb = match detect_type(contents) {
+		"" => return Err(ExifError::FileTypeUnknown),
+		"image/jpeg" => {
+			let mut source = contents;
+			find_photoshop_irb_in_jpeg(&mut source)
+		},
+		"image/vnd.adobe.photoshop" => {
+			let mut source = contents;
+			Some(find_photoshop_irb_in_psd(&mut source)?)
+		},
+		mime => match find_tiff_block(mime, contents, &ParseOptions::default()) {
+			Ok(tiff) => {
+				parse_tiff(&tiff)?.entries.into_iter()
+					.find(|e| e.ifd_kind == IfdKind::Ifd0 && e.ifd.tag == PHOTOSHOP_TAG)
+					.map(|e| e.ifd.data)
+			},
+			// no TIFF block, hence no tag
+			Err(_) => None,
+		},
+	};
+
+	match irb {
+		Some(irb) => parse_image_resources(&irb),
+		None => Ok(Vec::new()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testutil::image_resource as resource;
+
+	fn listing(resources: &[ImageResource]) -> Vec<(u16, &str, &[u8])>
+	{
+		resources.iter().map(|r| (r.id, &r.name[..], &r.data[..])).collect()
+	}
+
+	#[test]
+	fn padding() {
+		let mut irb = resource(b"8BIM", 0x0001, b"", b"odd");
+		irb.extend(resource(b"8BIM", 0x0002, b"", b"even"));
+		irb.extend(resource(b"8BIM", 0x0003, b"", b""));
+
+		assert_eq!(listing(&split_image_resources(&irb).unwrap()), vec![
+			(0x0001, "", &b"odd"[..]),
+			(0x0002, "", &b"even"[..]),
+			(0x0003, "", &b""[..]),
+		]);
+	}
+
+	#[test]
+	fn pascal_names() {
+		let mut irb = resource(b"8BIM", 0x0001, b"a", b"1");
+		irb.extend(resource(b"8BIM", 0x0002, b"ab", b"2"));
+		irb.extend(resource(b"PHUT", 0x0003, b"\xe9t\xe9", b"3"));
+
+		assert_eq!(listing(&split_image_resources(&irb).unwrap()), vec![
+			(0x0001, "a", &b"1"[..]),
+			(0x0002, "ab", &b"2"[..]),
+			(0x0003, "\u{e9}t\u{e9}", &b"3"[..]),
+		]);
+	}
+
+	#[test]
+	fn end_of_block() {
+		// the listing ends at an unknown signature, or a header that does not fit
+		let mut irb = resource(b"8BIM", 0x0001, b"", b"1");
+		irb.extend_from_slice(b"\0\0\0\0\0\0\0\0");
+		irb.extend(resource(b"8BIM", 0x0002, b"", b"2"));
+		assert_eq!(split_image_resources(&irb).unwrap().len(), 1);
+
+		let mut irb = resource(b"8BIM", 0x0001, b"", b"1");
+		irb.extend_from_slice(b"8BIM");
+		assert_eq!(split_image_resources(&irb).unwrap().len(), 1);
+	}
+
+	#[test]
+	fn truncated() {
+		let irb = resource(b"8BIM", 0x0001, b"name", b"data");
+		for &size in &[9, 13, 18] {
+			assert!(split_image_resources(&irb[..size]).is_err(), "size {}", size);
+		}
+	}
+
+	#[test]
+	fn decoded_values() {
+		let mut resolution = Vec::new();
+		for &(fixed, unit) in &[(0x012c_0000u32, 1u16), (0x0048_8000, 2)] {
+			resolution.extend_from_slice(&fixed.to_be_bytes());
+			resolution.extend_from_slice(&1u16.to_be_bytes());
+			resolution.extend_from_slice(&unit.to_be_bytes());
+		}
+		let mut irb = resource(b"8BIM", 0x03ed, b"", &resolution);
+		irb.extend(resource(b"8BIM", 0x0404, b"", b"\x1c\x02\x19\x00\x01k"));
+		irb.extend(resource(b"8BIM", 0x0422, b"", b"not a TIFF block"));
+
+		let resources = parse_image_resources(&irb).unwrap();
+		match resources[0].value {
+			ImageResourceValue::ResolutionInfo { x_resolution, x_resolution_unit, width_unit,
+						y_resolution, y_resolution_unit, height_unit } => {
+				assert_eq!((x_resolution, x_resolution_unit, width_unit), (300.0, 1, 1));
+				assert_eq!((y_resolution, y_resolution_unit, height_unit), (72.5, 1, 2));
+			},
+			ref value => panic!("not a resolution: {:?}", value),
+		}
+		match resources[1].value {
+			ImageResourceValue::Iptc(ref datasets) => assert_eq!(datasets[0].value, "k"),
+			ref value => panic!("not IPTC: {:?}", value),
+		}
+		// a resource that fails to decode is kept raw
+		match resources[2].value {
+			ImageResourceValue::Raw => {},
+			ref value => panic!("not raw: {:?}", value),
+		}
+	}
+
+	#[test]
+	fn tiff_tag() {
+		let irb = resource(b"8BIM", 0x0001, b"", b"tag");
+
+		// IFD0 with a single entry, tag 34377 of type UNDEFINED
+		let mut tiff = b"II*\0\x08\0\0\0\x01\0".to_vec();
+		tiff.extend_from_slice(&PHOTOSHOP_TAG.to_le_bytes());
+		tiff.extend_from_slice(&7u16.to_le_bytes());
+		tiff.extend_from_slice(&(irb.len() as u32).to_le_bytes());
+		tiff.extend_from_slice(&26u32.to_le_bytes());
+		tiff.extend_from_slice(&0u32.to_le_bytes());
+		tiff.extend_from_slice(&irb);
+
+		let mut blob = b"Exif\0\0".to_vec();
+		blob.extend_from_slice(&tiff);
+
+		for contents in &[tiff, blob] {
+			assert_eq!(listing(&read_image_resources(contents).unwrap()), vec![(0x0001, "", &b"tag"[..])]);
+		}
+	}
+}
@@ -0,0 +1,466 @@
+use super::types::*;
+use super::lowlevel::read_u32;
+use super::source::ByteSource;
+use super::md5::md5;
+use super::xmp::MAX_XMP_SIZE;
+
+/// Signature of the APP1 segment that holds the XMP packet
+const XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Signature of the APP1 segments that hold the parts of an extended XMP packet
+const XMP_EXTENSION_SIGNATURE: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+
+/// Signature of the APP13 segments that hold a Photoshop Image Resource Block
+const PHOTOSHOP_SIGNATURE: &[u8] = b"Photoshop 3.0\0";
+
+/// Signatures that tell the APPn segments apart: marker, signature and kind of segment
+static APP_SIGNATURES: [(u8, &[u8], JpegSegmentKind); 9] = [
+	(0xe0, b"JFIF\0", JpegSegmentKind::Jfif),
+	(0xe0, b"JFXX\0", JpegSegmentKind::Jfif),
+	(0xe1, b"Exif\0\0", JpegSegmentKind::Exif),
+	(0xe1, XMP_SIGNATURE, JpegSegmentKind::Xmp),
+	(0xe1, XMP_EXTENSION_SIGNATURE, JpegSegmentKind::XmpExtension),
+	(0xe2, b"ICC_PROFILE\0", JpegSegmentKind::IccProfile),
+	(0xe2, b"MPF\0", JpegSegmentKind::Mpf),
+	(0xed, PHOTOSHOP_SIGNATURE, JpegSegmentKind::Photoshop),
+	(0xee, b"Adobe", JpegSegmentKind::Adobe),
+];
+
+/// Length of the longest signature above
+const MAX_SIGNATURE_SIZE: usize = 35;
+
+/// Tells the kind of an APPn segment by its signature
+fn classify_app<S: ByteSource>(contents: &mut S, marker: u8, offset: usize, size: usize) -> JpegSegmentKind
+{
+	let start = contents.read_at(offset, size.min(MAX_SIGNATURE_SIZE)).unwrap_or_default();
+
+	for &(app, signature, kind) in APP_SIGNATURES.iter() {
+		if app == marker && start.starts_with(signature) {
+			return kind;
+		}
+	}
+
+	return JpegSegmentKind::App(marker - 0xe0);
+}
+
+/// Walks the JPEG markers from SOI up to SOS (or EOI) and appends each segment
+/// to `segments`. Fill bytes (0xFF padding before a marker) are skipped. On error,
+/// the segments found until then are kept in `segments`.
+fn scan_jpeg<S: ByteSource>(contents: &mut S, segments: &mut Vec<JpegSegment>) -> Result<(), ExifError>
+{
+	match contents.read_at(0, 2) {
+		Some(ref soi) if soi[..] == [0xff, 0xd8] => {},
+		_ => return Err(ExifError::JpegWithoutExif("SOI marker not found".to_string())),
+	}
+
+	let mut offset: usize = 2;
+
+	while offset < contents.size() {
+		let header = match contents.read_at(offset, 2) {
+			Some(header) => header,
+			None => return Err(ExifError::JpegWithoutExif("JPEG truncated in marker header".to_string())),
+		};
+
+		if header[0] != 0xff || header[1] == 0 {
+			let marker: u16 = u16::from(header[0]) * 256 + u16::from(header[1]);
+			return Err(ExifError::JpegWithoutExif(format!("Invalid marker {:x}", marker)))
+		}
+		if header[1] == 0xff {
+			// fill byte
+			offset += 1;
+			continue;
+		}
+
+		let marker = header[1];
+		offset += 2;
+
+		let kind = match marker {
+			0x01 | 0xd0..=0xd8 => JpegSegmentKind::Standalone,
+			0xd9 => JpegSegmentKind::EndOfImage,
+			_ => JpegSegmentKind::Other,
+		};
+		if kind != JpegSegmentKind::Other {
+			segments.push(JpegSegment { marker, kind, offset, size: 0 });
+			if kind == JpegSegmentKind::EndOfImage {
+				return Ok(());
+			}
+			continue;
+		}
+
+		let size = match contents.read_at(offset, 2) {
+			Some(raw) => (raw[0] as usize) * 256 + (raw[1] as usize),
+			None => return Err(ExifError::JpegWithoutExif("JPEG truncated in marker header".to_string())),
+		};
+
+		if size < 2 {
+			return Err(ExifError::JpegWithoutExif("JPEG marker size must be at least 2 (because of the size word)".to_string()))
+		}
+		if contents.size() < (offset + size) {
+			return Err(ExifError::JpegWithoutExif("JPEG truncated in marker body".to_string()))
+		}
+
+		let kind = match marker {
+			0xe0..=0xef => classify_app(contents, marker, offset + 2, size - 2),
+			0xfe => JpegSegmentKind::Comment,
+			0xda => JpegSegmentKind::StartOfScan,
+			_ => JpegSegmentKind::Other,
+		};
+		segments.push(JpegSegment { marker, kind, offset: offset + 2, size: size - 2 });
+
+		if kind == JpegSegmentKind::StartOfScan {
+			// compressed data follows
+			return Ok(());
+		}
+		offset += size;
+	}
+
+	return Ok(());
+}
+
+/// Lists the segments of a JPEG image, from the first one after SOI up to the
+/// start of the compressed data (SOS). APPn segments are classified by their
+/// signature, e.g. Exif and XMP segments, which share the APP1 marker.
+pub fn read_jpeg_segments(contents: &[u8]) -> Result<Vec<JpegSegment>, ExifError>
+{
+	let mut source = contents;
+	let mut segments: Vec<JpegSegment> = Vec::new();
+	scan_jpeg(&mut source, &mut segments)?;
+	return Ok(segments);
+}
+
+/// Find the embedded TIFF in a JPEG image (that in turn contains the EXIF data).
+/// Only the marker headers are read from the source, skipping over the marker
+/// bodies. The first APP1 segment with Exif signature is taken; other APP1
+/// segments (e.g. XMP) are skipped.
+pub fn find_embedded_tiff_in_jpeg<S: ByteSource>(contents: &mut S)
+								  -> Result<(usize, usize), ExifError>
+{
+	let mut segments: Vec<JpegSegment> = Vec::new();
+	let r = scan_jpeg(contents, &mut segments);
+
+	// A broken segment after the Exif segment does not matter
+	if let Some(exif) = segments.iter().find(|s| s.kind == JpegSegmentKind::Exif) {
+		// The offset and size of the block, excluding 'Exif\0\0'.
+		return Ok((exif.offset + 6, exif.size - 6));
+	}
+	r?;
+
+	match segments.last() {
+		Some(last) if last.kind == JpegSegmentKind::StartOfScan || last.kind == JpegSegmentKind::EndOfImage =>
+			Err(ExifError::JpegWithoutExif("Last mark found and no EXIF".to_string())),
+		_ => Err(ExifError::JpegWithoutExif("Scan past EOF and no EXIF found".to_string())),
+	}
+}
+
+/// Find the XMP packet of a JPEG image, in the first APP1 segment with XMP signature.
+/// Returns None if there is no such segment.
+pub fn find_xmp_in_jpeg<S: ByteSource>(contents: &mut S) -> Option<Vec<u8>>
+{
+	let mut segments: Vec<JpegSegment> = Vec::new();
+	// A broken segment after the XMP segment does not matter
+	let _ = scan_jpeg(contents, &mut segments);
+
+	let xmp = segments.iter().find(|s| s.kind == JpegSegmentKind::Xmp)?;
+	return contents.read_at(xmp.offset + XMP_SIGNATURE.len(), xmp.size - XMP_SIGNATURE.len());
+}
+
+/// Find the Photoshop Image Resource Block of a JPEG image. A large block is
+/// split across several APP13 segments, so their contents are concatenated.
+/// Returns None if there is no such segment.
+pub fn find_photoshop_irb_in_jpeg<S: ByteSource>(contents: &mut S) -> Option<Vec<u8>>
+{
+	let mut segments: Vec<JpegSegment> = Vec::new();
+	// A broken segment after the APP13 segments does not matter
+	let _ = scan_jpeg(contents, &mut segments);
+
+	let mut irb: Option<Vec<u8>> = None;
+	for segment in segments.iter().filter(|s| s.kind == JpegSegmentKind::Photoshop) {
+		let data = contents.read_at(segment.offset + PHOTOSHOP_SIGNATURE.len(),
+					segment.size - PHOTOSHOP_SIGNATURE.len())?;
+		irb.get_or_insert_with(Vec::new).extend_from_slice(&data);
+	}
+
+	return irb;
+}
+
+/// Reassembles the extended XMP packet with a given GUID, from the APP1 segments
+/// that hold its parts. Each part comes after the GUID (32 hexadecimal digits),
+/// the full length of the packet and the offset of the part. The GUID is the MD5
+/// digest of the whole packet, and it is verified.
+///
+/// Returns None if there is no such packet, if a part is missing, or if the
+/// packet does not match the GUID.
+pub fn find_extended_xmp_in_jpeg<S: ByteSource>(contents: &mut S, guid: &str) -> Option<Vec<u8>>
+{
+	let mut segments: Vec<JpegSegment> = Vec::new();
+	// A broken segment after the XMP segments does not matter
+	let _ = scan_jpeg(contents, &mut segments);
+
+	let header_size = XMP_EXTENSION_SIGNATURE.len() + 40;
+	let mut packet: Option<Vec<u8>> = None;
+	let mut parts: Vec<(usize, usize)> = Vec::new();
+
+	for segment in segments.iter().filter(|s| s.kind == JpegSegmentKind::XmpExtension) {
+		if segment.size < header_size {
+			continue;
+		}
+		let data = contents.read_at(segment.offset, segment.size)?;
+		let header = &data[XMP_EXTENSION_SIGNATURE.len()..header_size];
+		if !header[0..32].eq_ignore_ascii_case(guid.as_bytes()) {
+			// part of another extended packet
+			continue;
+		}
+
+		let length = read_u32(false, &header[32..36]) as usize;
+		let offset = read_u32(false, &header[36..40]) as usize;
+		if length > MAX_XMP_SIZE {
+			return None;
+		}
+
+		let packet = packet.get_or_insert_with(|| vec![0u8; length]);
+		let part = &data[header_size..];
+		let end = offset.checked_add(part.len())?;
+		if packet.len() != length || end > length {
+			return None;
+		}
+		packet[offset..end].copy_from_slice(part);
+		parts.push((offset, end));
+	}
+
+	// every byte of the packet must come from some part
+	let packet = packet?;
+	parts.sort();
+	let mut covered = 0;
+	for (start, end) in parts {
+		if start > covered {
+			return None;
+		}
+		covered = covered.max(end);
+	}
+	if covered != packet.len() {
+		return None;
+	}
+
+	let digest: String = md5(&packet).iter().map(|b| format!("{:02X}", b)).collect();
+	if !digest.eq_ignore_ascii_case(guid) {
+		return None;
+	}
+
+	return Some(packet);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Marker segment with a length word
+	fn segment(marker: u8, body: &[u8]) -> Vec<u8>
+	{
+		let mut segment = vec![0xff, marker];
+		segment.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+		segment.extend_from_slice(body);
+		return segment;
+	}
+
+	fn kinds(segments: &[JpegSegment]) -> Vec<(u8, JpegSegmentKind)>
+	{
+		return segments.iter().map(|s| (s.marker, s.kind)).collect();
+	}
+
+	#[test]
+	fn segment_kinds() {
+		let mut image = vec![0xff, 0xd8];
+		for &(marker, body) in [
+			(0xe0, &b"JFIF\0\x01\x02"[..]),
+			(0xe1, b"Exif\0\0MM\0\x2a"),
+			(0xe1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta/>"),
+			(0xe2, b"ICC_PROFILE\0\x01\x01"),
+			(0xe5, b"Unknown"),
+			(0xed, b"Photoshop 3.0\08BIM"),
+			(0xee, b"Adobe\0"),
+			(0xfe, b"comment"),
+			(0xdb, b"\0quantization"),
+			(0xda, b"\x01\x02"),
+		].iter() {
+			image.extend_from_slice(&segment(marker, body));
+		}
+		// compressed data, with a byte that looks like a marker
+		image.extend_from_slice(&[0x12, 0xff, 0xe1, 0x34, 0xff, 0xd9]);
+
+		let segments = read_jpeg_segments(&image).unwrap();
+		assert_eq!(kinds(&segments), vec![
+			(0xe0, JpegSegmentKind::Jfif),
+			(0xe1, JpegSegmentKind::Exif),
+			(0xe1, JpegSegmentKind::Xmp),
+			(0xe2, JpegSegmentKind::IccProfile),
+			(0xe5, JpegSegmentKind::App(5)),
+			(0xed, JpegSegmentKind::Photoshop),
+			(0xee, JpegSegmentKind::Adobe),
+			(0xfe, JpegSegmentKind::Comment),
+			(0xdb, JpegSegmentKind::Other),
+			(0xda, JpegSegmentKind::StartOfScan),
+		]);
+		assert_eq!((segments[0].offset, segments[0].size), (6, 7));
+		assert_eq!((segments[1].offset, segments[1].size), (17, 10));
+		assert_eq!(&image[segments[7].offset..segments[7].offset + segments[7].size], b"comment");
+	}
+
+	#[test]
+	fn fill_bytes_and_standalone_markers() {
+		let mut image = vec![0xff, 0xd8, 0xff, 0xff];
+		image.extend_from_slice(&segment(0xe1, b"Exif\0\0II\x2a\0"));
+		image.extend_from_slice(&[0xff, 0xff, 0xff, 0x01, 0xff, 0xd0]);
+		image.extend_from_slice(&segment(0xfe, b"comment"));
+		image.extend_from_slice(&[0xff, 0xd9]);
+
+		let segments = read_jpeg_segments(&image).unwrap();
+		assert_eq!(kinds(&segments), vec![
+			(0xe1, JpegSegmentKind::Exif),
+			(0x01, JpegSegmentKind::Standalone),
+			(0xd0, JpegSegmentKind::Standalone),
+			(0xfe, JpegSegmentKind::Comment),
+			(0xd9, JpegSegmentKind::EndOfImage),
+		]);
+		assert_eq!((segments[0].offset, segments[0].size), (8, 10));
+		assert_eq!(segments[1].size, 0);
+		assert_eq!(segments[4].offset, image.len());
+	}
+
+	#[test]
+	fn several_exif_segments() {
+		let mut image = vec![0xff, 0xd8];
+		image.extend_from_slice(&segment(0xe1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta/>"));
+		image.extend_from_slice(&segment(0xe1, b"Exif\0\0MM\0\x2a first"));
+		image.extend_from_slice(&segment(0xe1, b"Exif\0\0II\x2a\0 second"));
+		image.extend_from_slice(&[0xff, 0xd9]);
+
+		let segments = read_jpeg_segments(&image).unwrap();
+		assert_eq!(segments.iter().filter(|s| s.kind == JpegSegmentKind::Exif).count(), 2);
+
+		// the first Exif segment is taken
+		let mut source = &image[..];
+		let (offset, size) = find_embedded_tiff_in_jpeg(&mut source).unwrap();
+		assert_eq!(&image[offset..offset + size], b"MM\0\x2a first");
+	}
+
+	#[test]
+	fn broken_segments() {
+		let mut image = vec![0xff, 0xd8];
+		image.extend_from_slice(&segment(0xe0, b"JFIF\0\x01\x02"));
+		image.extend_from_slice(&segment(0xe1, b"Exif\0\0MM\0\x2a"));
+
+		// body past EOF
+		let mut truncated = image.clone();
+		truncated.extend_from_slice(&[0xff, 0xe2, 0x10, 0x00, b'I', b'C', b'C']);
+		assert!(read_jpeg_segments(&truncated).is_err());
+		// marker header cut short
+		let mut truncated = image.clone();
+		truncated.extend_from_slice(&[0xff, 0xe2, 0x10]);
+		assert!(read_jpeg_segments(&truncated).is_err());
+		// garbage instead of a marker
+		let mut garbage = image.clone();
+		garbage.extend_from_slice(&[0x12, 0x34, 0x56]);
+		assert!(read_jpeg_segments(&garbage).is_err());
+		// length word smaller than itself
+		let mut short = image.clone();
+		short.extend_from_slice(&[0xff, 0xe2, 0x00, 0x01]);
+		assert!(read_jpeg_segments(&short).is_err());
+
+		// the Exif segment before the broken one is still found
+		let mut source = &truncated[..];
+		assert!(find_embedded_tiff_in_jpeg(&mut source).is_ok());
+
+		assert!(read_jpeg_segments(b"\x89PNG\r\n\x1a\n").is_err());
+		// no segment at all
+		assert!(read_jpeg_segments(&[0xff, 0xd8]).unwrap().is_empty());
+	}
+
+	static PACKET: &[u8] = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">extended</x:xmpmeta>";
+
+	fn guid(packet: &[u8]) -> String
+	{
+		md5(packet).iter().map(|b| format!("{:02X}", b)).collect()
+	}
+
+	/// APP1 segment that holds the part of an extended XMP packet at `offset`
+	fn part(guid: &str, length: usize, offset: usize, data: &[u8]) -> Vec<u8>
+	{
+		let mut body = XMP_EXTENSION_SIGNATURE.to_vec();
+		body.extend_from_slice(guid.as_bytes());
+		body.extend_from_slice(&(length as u32).to_be_bytes());
+		body.extend_from_slice(&(offset as u32).to_be_bytes());
+		body.extend_from_slice(data);
+
+		let mut segment = vec![0xff, 0xe1];
+		segment.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+		segment.extend_from_slice(&body);
+		return segment;
+	}
+
+	/// JPEG image made of SOI, the given segments and EOI
+	fn jpeg(segments: &[Vec<u8>]) -> Vec<u8>
+	{
+		let mut jpeg = vec![0xff, 0xd8];
+		for segment in segments {
+			jpeg.extend_from_slice(segment);
+		}
+		jpeg.extend_from_slice(&[0xff, 0xd9]);
+		return jpeg;
+	}
+
+	fn extended(jpeg: &[u8], guid: &str) -> Option<Vec<u8>>
+	{
+		let mut source = jpeg;
+		return find_extended_xmp_in_jpeg(&mut source, guid);
+	}
+
+	#[test]
+	fn reassembly() {
+		let g = guid(PACKET);
+		let n = PACKET.len();
+		let (a, b, c) = (&PACKET[..10], &PACKET[10..30], &PACKET[30..]);
+
+		let in_order = jpeg(&[part(&g, n, 0, a), part(&g, n, 10, b), part(&g, n, 30, c)]);
+		assert_eq!(extended(&in_order, &g).as_ref().map(|p| &p[..]), Some(PACKET));
+
+		let out_of_order = jpeg(&[part(&g, n, 30, c), part(&g, n, 0, a), part(&g, n, 10, b)]);
+		assert_eq!(extended(&out_of_order, &g).as_ref().map(|p| &p[..]), Some(PACKET));
+
+		// the GUID is matched regardless of case
+		assert_eq!(extended(&in_order, &g.to_lowercase()).as_ref().map(|p| &p[..]), Some(PACKET));
+	}
+
+	#[test]
+	fn parts_of_other_packets() {
+		let g = guid(PACKET);
+		let other = guid(b"other");
+		let n = PACKET.len();
+
+		let mixed = jpeg(&[part(&other, 5, 0, b"other"), part(&g, n, 0, PACKET)]);
+		assert_eq!(extended(&mixed, &g).as_ref().map(|p| &p[..]), Some(PACKET));
+		assert_eq!(extended(&mixed, &other).as_ref().map(|p| &p[..]), Some(&b"other"[..]));
+		assert_eq!(extended(&mixed, &guid(b"none")), None);
+	}
+
+	#[test]
+	fn missing_part() {
+		let g = guid(PACKET);
+		let n = PACKET.len();
+
+		assert_eq!(extended(&jpeg(&[part(&g, n, 0, &PACKET[..10]), part(&g, n, 30, &PACKET[30..])]), &g), None);
+		assert_eq!(extended(&jpeg(&[part(&g, n, 0, &PACKET[..30])]), &g), None);
+	}
+
+	#[test]
+	fn guid_mismatch() {
+		let g = guid(PACKET);
+		let n = PACKET.len();
+
+		let mut altered = PACKET.to_vec();
+		altered[12] ^= 1;
+		assert_eq!(extended(&jpeg(&[part(&g, n, 0, &altered)]), &g), None);
+
+		// a part that goes beyond the full length
+		assert_eq!(extended(&jpeg(&[part(&g, n - 1, 0, PACKET)]), &g), None);
+	}
+}
@@ -0,0 +1,120 @@
+use super::types::ExifError;
+use super::lowlevel::read_u32;
+use super::source::ByteSource;
+use super::bmff::*;
+use super::xmp::MAX_XMP_SIZE;
+
+/// Find the EXIF data (a TIFF block) in a JPEG XL image. Only images in the
+/// ISO-BMFF container have metadata, in the "Exif" box. The box starts with the
+/// offset of the TIFF header, normally after an "Exif\0\0" prefix.
+/// Returns offset and size of the TIFF block.
+///
+/// Brotli-compressed boxes ("brob") are not supported. If the Exif box is
+/// compressed, an error says so. A bare codestream, without container, is
+/// recognized as JPEG XL as well, and fails with an error that says it has
+/// no metadata.
+pub fn find_embedded_tiff_in_jxl<S: ByteSource>(contents: &mut S)
+					-> Result<(usize, usize), ExifError>
+{
+	if contents.read_at(0, 2).is_some_and(|signature| signature[..] == [0xff, 0x0a]) {
+		return Err(ExifError::JxlWithoutExif("Bare JPEG XL codestream carries no metadata".to_string()));
+	}
+
+	let size = contents.size();
+	let mut compressed = false;
+
+	for b in read_boxes(contents, 0, size) {
+		if b.box_type == *b"brob" {
+			// the type of the compressed box comes first
+			if let Some(inner) = contents.read_at(b.offset, 4) {
+				compressed |= inner[..] == b"Exif"[..];
+			}
+			continue;
+		}
+		if b.box_type != *b"Exif" {
+			continue;
+		}
+
+		let tiff_offset = match contents.read_at(b.offset, 4) {
+			Some(raw) => read_u32(false, &raw) as usize,
+			None => return Err(ExifError::JxlWithoutExif("Exif box truncated".to_string())),
+		};
+		if tiff_offset > b.size.saturating_sub(4) {
+			return Err(ExifError::JxlWithoutExif("Exif box truncated".to_string()));
+		}
+		return Ok((b.offset + 4 + tiff_offset, b.size - 4 - tiff_offset));
+	}
+
+	if compressed {
+		return Err(ExifError::JxlWithoutExif("Brotli-compressed Exif box is not supported".to_string()));
+	}
+	return Err(ExifError::JxlWithoutExif("No Exif box found".to_string()));
+}
+
+/// Find the XMP packet of a JPEG XL image, stored in the "xml " box of the
+/// ISO-BMFF container. A Brotli-compressed box is skipped.
+/// Returns None if there is no such box.
+pub fn find_xmp_in_jxl<S: ByteSource>(contents: &mut S) -> Option<Vec<u8>>
+{
+	let size = contents.size();
+	let xml = read_boxes(contents, 0, size).into_iter()
+		.find(|b| b.box_type == *b"xml ")?;
+	if xml.size > MAX_XMP_SIZE {
+		return None;
+	}
+	return contents.read_at(xml.offset, xml.size);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testutil::*;
+
+	const SIGNATURE: &[u8] = b"\0\0\0\x0cJXL \r\n\x87\n";
+
+	fn jxl(boxes: &[Vec<u8>]) -> Vec<u8>
+	{
+		let mut image = SIGNATURE.to_vec();
+		image.extend(bmff_box(b"ftyp", b"jxl \0\0\0\0jxl "));
+		for b in boxes {
+			image.extend_from_slice(b);
+		}
+		image.extend(bmff_box(b"jxlc", b"\xff\x0acodestream"));
+		return image;
+	}
+
+	fn error(image: &[u8]) -> String
+	{
+		return find_embedded_tiff_in_jxl(&mut &image[..]).unwrap_err().to_string();
+	}
+
+	#[test]
+	fn exif_box() {
+		let tiff = sample_exif(false);
+		let image = jxl(&[bmff_box(b"Exif", &[&[0, 0, 0, 6][..], b"Exif\0\0", &tiff].concat())]);
+		let (offset, size) = find_embedded_tiff_in_jxl(&mut &image[..]).unwrap();
+		assert_eq!(&image[offset..offset + size], &tiff[..]);
+
+		let image = jxl(&[bmff_box(b"Exif", &[&[0, 0, 0, 0][..], &tiff].concat())]);
+		let (offset, size) = find_embedded_tiff_in_jxl(&mut &image[..]).unwrap();
+		assert_eq!(&image[offset..offset + size], &tiff[..]);
+		assert_eq!(::parse_buffer(&image).unwrap().mime, "image/jxl");
+	}
+
+	#[test]
+	fn no_exif_box() {
+		let image = jxl(&[bmff_box(b"brob", b"Exif compressed data")]);
+		assert!(error(&image).contains("Brotli"));
+
+		let image = jxl(&[bmff_box(b"Exif", &[0, 0, 0, 8, 0, 0])]);
+		assert!(error(&image).contains("truncated"));
+
+		let image = jxl(&[]);
+		assert!(error(&image).contains("No Exif box"));
+
+		let codestream = b"\xff\x0a\xfa\x7f\x01\x90\x08\x06\x01\x00\x48";
+		assert_eq!(::detect_type(codestream), "image/jxl");
+		assert!(error(codestream).contains("no metadata"));
+		assert!(::parse_buffer(codestream).unwrap_err().to_string().contains("no metadata"));
+	}
+}
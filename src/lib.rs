@@ -1,4 +1,4 @@
-//! RExif is a native Rust create, written to extract EXIF data from JPEG and TIFF images.
+//! RExif is a native Rust create, written to extract EXIF data from JPEG, TIFF, PNG, WebP, HEIF, JPEG XL, Canon CR3, Fujifilm RAF and Adobe PSD/PSB images.
 //! 
 //! Note that it is in very early stages of development. Any sort of feedback is welcome!
 //!
@@ -12,6 +12,8 @@
 //!
 //! To learn to use this crate, start by the documentation of function `parse_file()`, 
 //! and the struct `ExifData` that is returned by the parser. The rest falls more or less into place.
+//! The XMP packet of the image, if any, is parsed as well, see `ExifData::xmp`,
+//! and so is the IPTC-IIM record, see `ExifData::iptc`.
 //!
 //! Code sample lightly edited from src/bin.rs: 
 //!
@@ -35,6 +37,10 @@
 //! }
 //! ```
 
+// Explicit returns and tab-indented doc samples are the house style
+#![allow(clippy::needless_return, clippy::tabs_in_doc_comments)]
+
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::{Seek,SeekFrom,Read};
 use std::path::Path;
@@ -46,51 +52,214 @@ mod types;
 pub use self::types::*;
 mod types_impl;
 pub use self::types_impl::*;
-mod debug;
 mod image;
 use self::image::*;
 mod ifdformat;
 mod tiff;
 use self::tiff::*;
+pub use self::tiff::{parse_tiff, parse_tiff_with_options};
 mod exifreadable;
 mod exifpost;
 mod exif;
+mod source;
+use self::source::*;
+mod inflate;
+mod png;
+use self::png::*;
+mod webp;
+use self::webp::*;
+mod bmff;
+mod heif;
+use self::heif::*;
+mod jxl;
+use self::jxl::*;
+mod cr3;
+use self::cr3::*;
+mod raf;
+use self::raf::*;
+mod psd;
+use self::psd::*;
+mod jpeg;
+use self::jpeg::*;
+pub use self::jpeg::read_jpeg_segments;
+mod xmp;
+mod md5;
+use self::xmp::*;
+pub use self::xmp::parse_xmp;
+mod irb;
+pub use self::irb::{parse_image_resources, read_image_resources};
+mod iptc;
+use self::iptc::*;
+pub use self::iptc::parse_iptc;
+#[cfg(test)]
+mod testutil;
+
+/// Location of the TIFF block of an image
+enum TiffBlock {
+	/// Offset and size of the block within the image
+	Embedded(usize, usize),
+	/// Block that the container format stores compressed or split, and was
+	/// extracted from it
+	Extracted(Vec<u8>),
+}
+
+/// Finds the TIFF block of an image of a given type. For CR3, it is the block
+/// of the main image IFD0.
+fn locate_tiff_block<S: ByteSource>(mime: &str, contents: &mut S, options: &ParseOptions) -> Result<TiffBlock, ExifError>
+{
+	let (offset, size) = match mime {
+		"" => return Err(ExifError::FileTypeUnknown),
+		"image/jpeg" => find_embedded_tiff_in_jpeg(contents)?,
+		"image/png" => return Ok(TiffBlock::Extracted(find_exif_in_png(contents, options.check_crc)?)),
+		"image/heic" | "image/heif" | "image/avif" => return Ok(TiffBlock::Extracted(find_exif_in_heif(contents)?)),
+		"image/vnd.adobe.photoshop" => return Ok(TiffBlock::Extracted(find_exif_in_psd(contents)?)),
+		"image/webp" => find_embedded_tiff_in_webp(contents)?,
+		"image/jxl" => find_embedded_tiff_in_jxl(contents)?,
+		"image/x-fuji-raf" => find_embedded_tiff_in_raf(contents)?,
+		"image/x-canon-cr3" => find_embedded_tiff_in_cr3(contents)?,
+		"application/x-exif" => (EXIF_PREFIX.len(), contents.size() - EXIF_PREFIX.len()),
+		_ => (0, contents.size()),
+	};
+
+	return Ok(TiffBlock::Embedded(offset, size));
+}
+
+/// Finds the TIFF block of an image of a given type held in a byte buffer.
+/// The block is borrowed from the buffer, unless the container format stores
+/// it compressed or split.
+fn find_tiff_block<'a>(mime: &str, contents: &'a [u8], options: &ParseOptions) -> Result<Cow<'a, [u8]>, ExifError>
+{
+	let mut source = contents;
+	return match locate_tiff_block(mime, &mut source, options)? {
+		TiffBlock::Embedded(offset, size) => Ok(Cow::Borrowed(&contents[offset .. offset + size])),
+		TiffBlock::Extracted(block) => Ok(Cow::Owned(block)),
+	};
+}
+
+/// Parses an image of a given type: the EXIF data, and the XMP packet and
+/// IPTC record that the container stores outside of the TIFF block. Shared
+/// by the parsing of buffers and streams.
+fn parse_source<S: ByteSource>(mime: &str, contents: &mut S, options: &ParseOptions) -> ExifResult
+{
+	let mut d = match mime {
+		"image/x-canon-cr3" => {
+			// metadata is split across several TIFF blocks
+			parse_cr3(contents, options)?
+		},
+		_ => match locate_tiff_block(mime, contents, options)? {
+			TiffBlock::Embedded(offset, size) => {
+				parse_tiff_source(&mut SubSource::new(contents, offset, size), options)?
+			},
+			TiffBlock::Extracted(block) => parse_tiff_with_options(&block, options)?,
+		}
+	};
 
-/// Parse a byte buffer that should contain a TIFF or JPEG image.
+	if let Some(xmp) = read_container_xmp(mime, contents) {
+		d.xmp = xmp;
+	}
+	if let Some(iptc) = read_container_iptc(mime, contents) {
+		d.iptc = iptc;
+	}
+
+	d.mime = if mime == "image/tiff" {
+		// may be a raw format, that is a plain TIFF told apart by its IFDs
+		match parse_tiff_pages_source(contents, options) {
+			Ok(ref pages) if !pages.is_empty() => classify_tiff(&pages[0]),
+			_ => "image/tiff",
+		}.to_string()
+	} else {
+		mime.to_string()
+	};
+
+	Ok(d)
+}
+
+/// Parse a byte buffer that should contain a TIFF, JPEG, PNG, WebP, HEIF, JPEG XL, CR3, RAF or PSD image.
 /// Tries to detect format and parse EXIF data.
+/// A bare EXIF payload with "Exif\0\0" header is accepted as well, see `parse_exif_blob`.
 pub fn parse_buffer(contents: &[u8]) -> ExifResult
+{
+	parse_buffer_with_options(contents, &ParseOptions::default())
+}
+
+/// Same as `parse_buffer`, with parser options, e.g. lenient mode
+pub fn parse_buffer_with_options(contents: &[u8], options: &ParseOptions) -> ExifResult
 {
 	let mime = detect_type(contents);
+	let mut source = contents;
+	parse_source(mime, &mut source, options)
+}
 
-	let d = match mime {
-		"" => return Err(ExifError::FileTypeUnknown),
-		"image/jpeg" => {
-			let (offset, size) = find_embedded_tiff_in_jpeg(contents)?;
-			// println!("Offset {} size {}", offset, size);
-			parse_tiff(&contents[offset .. offset + size])?
-		},
-		_ => {
-			parse_tiff(contents)?
-		}
+/// Parse a byte buffer that should contain a TIFF, JPEG, PNG, WebP, HEIF, JPEG XL, CR3, RAF or PSD image, and returns
+/// every IFD of the TIFF IFD chain separately. For a multi-page TIFF, each
+/// `IfdDirectory` is a page. For a JPEG, there are at most two IFDs: the main
+/// image and the thumbnail. For a CR3, only the IFD0 of the main image is
+/// returned, since the other CR3 blocks are not part of the chain.
+pub fn parse_buffer_pages(contents: &[u8]) -> IfdDirectoryResult
+{
+	parse_buffer_pages_with_options(contents, &ParseOptions::default())
+}
+
+/// Same as `parse_buffer_pages`, with parser options, e.g. lenient mode
+pub fn parse_buffer_pages_with_options(contents: &[u8], options: &ParseOptions) -> IfdDirectoryResult
+{
+	let tiff = find_tiff_block(detect_type(contents), contents, options)?;
+	parse_tiff_pages(&tiff, options)
+}
+
+/// Parse an image from a seekable stream, e.g. an open file. Only the parts
+/// of the stream that contain the EXIF data are read: JPEG marker headers,
+/// IFD tables and the data blocks they point to. Large images and files
+/// on network storage are not loaded whole into memory.
+pub fn parse_reader<R: Read + Seek>(reader: &mut R) -> ExifResult
+{
+	parse_reader_with_options(reader, &ParseOptions::default())
+}
+
+/// Same as `parse_reader`, with parser options, e.g. lenient mode
+pub fn parse_reader_with_options<R: Read + Seek>(reader: &mut R, options: &ParseOptions) -> ExifResult
+{
+	let size = reader.seek(SeekFrom::End(0))? as usize;
+
+	// enough to detect the image type
+	let mut header: Vec<u8> = vec![0; size.min(64)];
+	reader.seek(SeekFrom::Start(0))?;
+	reader.read_exact(&mut header)?;
+
+	let mime = detect_type(&header);
+
+	let mut source = ReaderSource::new(reader, 0, size);
+	let r = parse_source(mime, &mut source, options);
+	source.check()?;
+	r
+}
+
+/// Parse a bare EXIF payload, as handed over by some APIs (browsers, camera SDKs,
+/// HEIF extractors): a TIFF block, optionally preceded by the "Exif\0\0" header
+/// of the JPEG APP1 segment. The `mime` member of the result is "application/x-exif".
+pub fn parse_exif_blob(contents: &[u8]) -> ExifResult
+{
+	parse_exif_blob_with_options(contents, &ParseOptions::default())
+}
+
+/// Same as `parse_exif_blob`, with parser options
+pub fn parse_exif_blob_with_options(contents: &[u8], options: &ParseOptions) -> ExifResult
+{
+	let tiff = if contents.starts_with(EXIF_PREFIX) {
+		&contents[EXIF_PREFIX.len()..]
+	} else {
+		contents
 	};
 
-	Ok(ExifData {
-		mime: mime.to_string(),
-		entries: d,
-	})
+	let mut d = parse_tiff_with_options(tiff, options)?;
+	d.mime = "application/x-exif".to_string();
+	Ok(d)
 }
 
 /// Try to read and parse an open file that is expected to contain an image
 pub fn read_file(f: &mut File) -> ExifResult
 {
-	f.seek(SeekFrom::Start(0))?;
-
-	// TODO: should read only the relevant parts of a file,
-	// and pass a StringIO-like object instead of a Vec buffer
-
-	let mut contents: Vec<u8> = Vec::new();
-	f.read_to_end(&mut contents)?;
-	parse_buffer(&contents)
+	parse_reader(f)
 }
 
 /// Opens an image (passed as a file name), tries to read and parse it.
@@ -98,3 +267,113 @@ pub fn parse_file<P: AsRef<Path>>(fname: P) -> ExifResult
 {
 	read_file(&mut File::open(fname)?)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{self, Cursor};
+	use testutil::*;
+
+	/// Reader that counts the bytes read
+	struct CountingReader {
+		inner: Cursor<Vec<u8>>,
+		read: usize,
+	}
+
+	impl Read for CountingReader {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+		{
+			let n = self.inner.read(buf)?;
+			self.read += n;
+			return Ok(n);
+		}
+	}
+
+	impl Seek for CountingReader {
+		fn seek(&mut self, pos: SeekFrom) -> io::Result<u64>
+		{
+			self.inner.seek(pos)
+		}
+	}
+
+	fn summary(d: &ExifData) -> Vec<String>
+	{
+		let mut summary: Vec<String> = d.entries.iter()
+			.map(|e| format!("{:?} {:x} {}", e.ifd_kind, e.ifd.tag, e.value_more_readable))
+			.collect();
+		summary.push(d.mime.clone());
+		summary.push(format!("{:?}", d.thumbnail()));
+		return summary;
+	}
+
+	#[test]
+	fn exif_blob() {
+		for &le in &[true, false] {
+			let tiff = sample_exif(le);
+			let mut prefixed = EXIF_PREFIX.to_vec();
+			prefixed.extend_from_slice(&tiff);
+
+			let expected = parse_tiff(&tiff).unwrap();
+			for blob in &[&tiff, &prefixed] {
+				let d = parse_exif_blob(blob).unwrap();
+				assert_eq!(d.mime, "application/x-exif");
+				assert_eq!(summary(&d)[..expected.entries.len()], summary(&expected)[..expected.entries.len()]);
+				assert_eq!(d.thumbnail(), expected.thumbnail());
+			}
+
+			// recognized by parse_buffer with the prefix, or as a TIFF without it
+			let d = parse_buffer(&prefixed).unwrap();
+			assert_eq!(d.mime, "application/x-exif");
+			assert_eq!(d.entries.len(), expected.entries.len());
+			assert_eq!(parse_buffer(&tiff).unwrap().mime, "image/tiff");
+		}
+
+		assert!(parse_exif_blob(b"Exif\0\0").is_err());
+		assert!(parse_exif_blob(b"Exif\0\0GIF89a").is_err());
+		assert!(parse_exif_blob(b"").is_err());
+	}
+
+	#[test]
+	fn reader_and_buffer() {
+		let jpeg = jpeg_with_exif(&sample_exif(false));
+		let tiff = sample_exif(true);
+		for image in &[jpeg, tiff] {
+			let from_buffer = parse_buffer(image).unwrap();
+			let from_reader = parse_reader(&mut Cursor::new(image)).unwrap();
+			assert_eq!(summary(&from_buffer), summary(&from_reader));
+			assert!(from_buffer.entries.len() > 10);
+
+			// broken images fail in the same way
+			let cut = &image[..image.len() / 3];
+			let from_buffer = parse_buffer(cut).map(|d| summary(&d)).map_err(|e| e.to_string());
+			let from_reader = parse_reader(&mut Cursor::new(cut)).map(|d| summary(&d)).map_err(|e| e.to_string());
+			assert_eq!(from_buffer, from_reader);
+		}
+	}
+
+	#[test]
+	fn reader_reads_little() {
+		// compressed data of a large image follows the Exif segment
+		let mut jpeg = jpeg_with_exif(&sample_exif(true));
+		let eoi = jpeg.split_off(jpeg.len() - 2);
+		jpeg.resize(jpeg.len() + 8 * 1024 * 1024, 0x55);
+		jpeg.extend(eoi);
+
+		// TIFF whose IFD0 comes after the image data
+		let mut tiff = Tiff::new(true);
+		let strip = tiff.blob(&vec![0x55; 8 * 1024 * 1024]);
+		let ifd0 = tiff.ifd(&[
+			entry(0x0100, 4, &[4096]),
+			entry(0x0111, 4, &[strip]),
+			entry(0x0117, 4, &[8 * 1024 * 1024]),
+		], 0);
+		let tiff = tiff.finish(ifd0);
+
+		for image in [jpeg, tiff] {
+			let mut reader = CountingReader { inner: Cursor::new(image), read: 0 };
+			let d = parse_reader(&mut reader).unwrap();
+			assert!(!d.entries.is_empty());
+			assert!(reader.read < 4096, "{} bytes read", reader.read);
+		}
+	}
+}
@@ -1,12 +1,20 @@
-use std::mem;
 use super::rational::*;
 
+/// Get a sub-slice of a buffer, or None if it would go beyond the end of buffer.
+/// Offset and length may come from a hostile file, so the sum is checked
+/// for overflow as well.
+pub fn slice_at(contents: &[u8], offset: usize, length: usize) -> Option<&[u8]>
+{
+	let end = offset.checked_add(length)?;
+	contents.get(offset..end)
+}
+
 /// Convert u8 to i8
 pub fn read_i8(raw: u8) -> i8
 {
 	let mut u = i16::from(raw);
 	if u >= 0x80 {
-		u = u - 0x100;
+		u -= 0x100;
 	}
 	return u as i8;
 }
@@ -26,7 +34,7 @@ pub fn read_i16(le: bool, raw: &[u8]) -> i16
 {
 	let mut u = i32::from(read_u16(le, raw));
 	if u >= 0x8000 {
-		u = u - 0x10000;
+		u -= 0x10000;
 	}
 	return u as i16;
 }
@@ -43,41 +51,52 @@ pub fn read_u32(le: bool, raw: &[u8]) -> u32
 	}
 }
 
+/// Read value from a stream of bytes
+pub fn read_u64(le: bool, raw: &[u8]) -> u64
+{
+	let a = u64::from(read_u32(le, &raw[0..4]));
+	let b = u64::from(read_u32(le, &raw[4..8]));
+	if le {
+		(b << 32) + a
+	} else {
+		(a << 32) + b
+	}
+}
+
 /// Read value from a stream of bytes
 pub fn read_i32(le: bool, raw: &[u8]) -> i32
 {
 	let mut u = read_u32(le, raw) as i64;
 	if u >= 0x80000000 {
-		u = u - 0x100000000;
+		u -= 0x100000000;
 	}
 	return u as i32;
 }
 
+/// Read value from a stream of bytes
+pub fn read_i64(le: bool, raw: &[u8]) -> i64
+{
+	return read_u64(le, raw) as i64;
+}
+
 /// Read value from a stream of bytes
 pub fn read_f32(raw: &[u8]) -> f32
 {
-	let mut a = [0 as u8; 4];
-	// idiot, but guarantees that transmute gets a 4-byte buffer
-	for i in 0..4 {
-		a[i] = raw[i];
-	}
+	let mut a = [0u8; 4];
+	a.copy_from_slice(&raw[0..4]);
 	// FIXME I am not sure that TIFF floating point can be cast this way for any given architecture
 	// The ideal thing would be to read mantissa, exponent, etc. explicitly
-	let f: f32 = unsafe { mem::transmute(a) }; 
-	return f;
+	return f32::from_ne_bytes(a);
 }
 
 /// Read value from a stream of bytes
 pub fn read_f64(raw: &[u8]) -> f64
 {
-	let mut a = [0 as u8; 8];
-	for i in 0..8 {
-		a[i] = raw[i];
-	}
+	let mut a = [0u8; 8];
+	a.copy_from_slice(&raw[0..8]);
 	// FIXME I am not sure that TIFF floating point can be cast this way for any given architecture
 	// The ideal thing would be to read mantissa, exponent, etc. explicitly
-	let f: f64 = unsafe { mem::transmute(a) };
-	return f;
+	return f64::from_ne_bytes(a);
 }
 
 /// Read value from a stream of bytes
@@ -154,6 +173,30 @@ pub fn read_i32_array(le: bool, count: u32, raw: &[u8]) -> Vec<i32>
 	return a;
 }
 
+/// Read array from a stream of bytes. Caller must be sure of count and buffer size
+pub fn read_u64_array(le: bool, count: u32, raw: &[u8]) -> Vec<u64>
+{
+	let mut a = Vec::<u64>::new();
+	let mut offset = 0;
+	for _ in 0..count {
+		a.push(read_u64(le, &raw[offset..offset + 8]));
+		offset += 8;
+	}
+	return a;
+}
+
+/// Read array from a stream of bytes. Caller must be sure of count and buffer size
+pub fn read_i64_array(le: bool, count: u32, raw: &[u8]) -> Vec<i64>
+{
+	let mut a = Vec::<i64>::new();
+	let mut offset = 0;
+	for _ in 0..count {
+		a.push(read_i64(le, &raw[offset..offset + 8]));
+		offset += 8;
+	}
+	return a;
+}
+
 /// Read array from a stream of bytes. Caller must be sure of count and buffer size
 pub fn read_f32_array(count: u32, raw: &[u8]) -> Vec<f32>
 {
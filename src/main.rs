@@ -14,7 +14,7 @@ fn main()
 		process::exit(2);
 	}
 	for arg in &args[1..] {
-		match rexif::parse_file(&arg) {
+		match rexif::parse_file(arg) {
 			Ok(exif) => {
 				println!("{} {} exif entries: {}",
 					arg, exif.mime, exif.entries.len());
@@ -30,6 +30,22 @@ fn main()
 								entry.value_more_readable);
 					}
 				}
+				for property in &exif.xmp {
+					println!("	XMP {}:{}: {}",
+							property.prefix, property.name,
+							property.value);
+				}
+				for dataset in &exif.iptc {
+					println!("	IPTC {}:{} {}: {}",
+							dataset.record, dataset.dataset,
+							dataset.name, dataset.value);
+				}
+				if let Some(thumbnail) = exif.thumbnail() {
+					println!("	Thumbnail: {} bytes", thumbnail.len());
+				}
+				for warning in &exif.warnings {
+					eprintln!("Warning in {}: {}", &arg, warning);
+				}
 			},
 			Err(e) => {
 				eprintln!("Error in {}: {}", &arg, e);
@@ -0,0 +1,98 @@
+/// Per-round shift amounts of MD5 (RFC 1321)
+static SHIFTS: [u32; 64] = [
+	7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+	5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+	4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+	6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// Per-round constants of MD5, the integer part of abs(sin(i + 1)) * 2^32
+static CONSTANTS: [u32; 64] = [
+	0xd76a_a478, 0xe8c7_b756, 0x2420_70db, 0xc1bd_ceee, 0xf57c_0faf, 0x4787_c62a, 0xa830_4613, 0xfd46_9501,
+	0x6980_98d8, 0x8b44_f7af, 0xffff_5bb1, 0x895c_d7be, 0x6b90_1122, 0xfd98_7193, 0xa679_438e, 0x49b4_0821,
+	0xf61e_2562, 0xc040_b340, 0x265e_5a51, 0xe9b6_c7aa, 0xd62f_105d, 0x0244_1453, 0xd8a1_e681, 0xe7d3_fbc8,
+	0x21e1_cde6, 0xc337_07d6, 0xf4d5_0d87, 0x455a_14ed, 0xa9e3_e905, 0xfcef_a3f8, 0x676f_02d9, 0x8d2a_4c8a,
+	0xfffa_3942, 0x8771_f681, 0x6d9d_6122, 0xfde5_380c, 0xa4be_ea44, 0x4bde_cfa9, 0xf6bb_4b60, 0xbebf_bc70,
+	0x289b_7ec6, 0xeaa1_27fa, 0xd4ef_3085, 0x0488_1d05, 0xd9d4_d039, 0xe6db_99e5, 0x1fa2_7cf8, 0xc4ac_5665,
+	0xf429_2244, 0x432a_ff97, 0xab94_23a7, 0xfc93_a039, 0x655b_59c3, 0x8f0c_cc92, 0xffef_f47d, 0x8584_5dd1,
+	0x6fa8_7e4f, 0xfe2c_e6e0, 0xa301_4314, 0x4e08_11a1, 0xf753_7e82, 0xbd3a_f235, 0x2ad7_d2bb, 0xeb86_d391,
+];
+
+/// Computes the MD5 digest of a buffer (RFC 1321). Used to verify the GUID
+/// of extended XMP, not for anything related to security.
+pub fn md5(data: &[u8]) -> [u8; 16]
+{
+	let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+	// padding: 0x80, zeros up to 56 mod 64, then the length in bits (LE)
+	let mut message = data.to_vec();
+	message.push(0x80);
+	while message.len() % 64 != 56 {
+		message.push(0);
+	}
+	message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+	for block in message.chunks(64) {
+		let mut words = [0u32; 16];
+		for (i, word) in words.iter_mut().enumerate() {
+			*word = u32::from_le_bytes([block[i * 4], block[i * 4 + 1], block[i * 4 + 2], block[i * 4 + 3]]);
+		}
+
+		let [mut a, mut b, mut c, mut d] = state;
+		for i in 0..64 {
+			let (f, g) = match i / 16 {
+				0 => ((b & c) | (!b & d), i),
+				1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+				2 => (b ^ c ^ d, (3 * i + 5) % 16),
+				_ => (c ^ (b | !d), (7 * i) % 16),
+			};
+			let f = f.wrapping_add(a).wrapping_add(CONSTANTS[i]).wrapping_add(words[g]);
+			a = d;
+			d = c;
+			c = b;
+			b = b.wrapping_add(f.rotate_left(SHIFTS[i]));
+		}
+
+		state[0] = state[0].wrapping_add(a);
+		state[1] = state[1].wrapping_add(b);
+		state[2] = state[2].wrapping_add(c);
+		state[3] = state[3].wrapping_add(d);
+	}
+
+	let mut digest = [0u8; 16];
+	for (i, word) in state.iter().enumerate() {
+		digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+	}
+	return digest;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::md5;
+
+	fn hex_digest(data: &[u8]) -> String
+	{
+		md5(data).iter().map(|b| format!("{:02x}", b)).collect()
+	}
+
+	#[test]
+	fn rfc1321_test_suite() {
+		// RFC 1321, appendix A.5
+		assert_eq!(hex_digest(b""), "d41d8cd98f00b204e9800998ecf8427e");
+		assert_eq!(hex_digest(b"a"), "0cc175b9c0f1b6a831c399e269772661");
+		assert_eq!(hex_digest(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+		assert_eq!(hex_digest(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+		assert_eq!(hex_digest(b"abcdefghijklmnopqrstuvwxyz"), "c3fcd3d76192e4007dfb496cca67e13b");
+		assert_eq!(hex_digest(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"),
+			"d174ab98d277d9f5a5611c2c9f419d9f");
+		assert_eq!(hex_digest("1234567890".repeat(8).as_bytes()), "57edf4a22be3c955ac49da2e2107b67a");
+	}
+
+	#[test]
+	fn padding_boundaries() {
+		// the length goes in the same block as the data up to 55 bytes, else in a new one
+		assert_eq!(hex_digest(&[b'a'; 55]), "ef1772b6dff9a122358552954ad0df65");
+		assert_eq!(hex_digest(&[b'a'; 56]), "3b0c8ac703f828b04c6c197006d17218");
+		assert_eq!(hex_digest(&[b'a'; 64]), "014842d480b571495a4a0363793f7367");
+	}
+}
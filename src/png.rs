@@ -0,0 +1,306 @@
+use super::types::ExifError;
+use super::lowlevel::read_u32;
+use super::source::ByteSource;
+use super::inflate::zlib_decompress;
+
+/// Largest decompressed text chunk accepted. Protects against zip bombs.
+const MAX_TEXT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Keyword of the iTXt chunk that holds the XMP packet
+const XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+
+/// Keywords of the legacy text chunks that hold EXIF data
+const EXIF_KEYWORDS: [&[u8]; 2] = [b"Raw profile type exif", b"Raw profile type APP1"];
+
+/// Largest keyword of a text chunk (79 characters), with the NUL that ends it
+const MAX_KEYWORD_SIZE: usize = 80;
+
+/// Computes the CRC-32 of PNG chunks (ISO 3309)
+fn crc32(parts: &[&[u8]]) -> u32
+{
+	let mut crc: u32 = 0xffff_ffff;
+	for part in parts {
+		for &byte in *part {
+			crc ^= u32::from(byte);
+			for _ in 0..8 {
+				crc = if crc & 1 != 0 {(crc >> 1) ^ 0xedb8_8320} else {crc >> 1};
+			}
+		}
+	}
+	return !crc;
+}
+
+/// Removes the "Exif\0\0" prefix that some encoders put before the TIFF block
+fn strip_exif_prefix(mut data: Vec<u8>) -> Vec<u8>
+{
+	if data.starts_with(b"Exif\0\0") {
+		data.drain(..6);
+	}
+	return data;
+}
+
+/// Decodes the text of a "Raw profile type exif" chunk, as written by ImageMagick
+/// and exiftool before the eXIf chunk existed: a line with the profile name, a
+/// line with the length of the data, and then the data as hexadecimal digits.
+fn decode_raw_profile(text: &[u8]) -> Option<Vec<u8>>
+{
+	let mut words = text.split(|c| c.is_ascii_whitespace()).filter(|w| !w.is_empty());
+	let _name = words.next()?;
+	let length: usize = String::from_utf8_lossy(words.next()?).parse().ok()?;
+
+	let mut data: Vec<u8> = Vec::with_capacity(length.min(MAX_TEXT_SIZE));
+	let mut digits = words.flat_map(|w| w.iter()).map(|&c| (c as char).to_digit(16));
+
+	while data.len() < length {
+		let high = digits.next()??;
+		let low = digits.next()??;
+		data.push((high * 16 + low) as u8);
+	}
+
+	return Some(data);
+}
+
+/// Gets the text of an iTXt chunk, given the data that follows the keyword:
+/// compression flag, compression method, language tag, translated keyword and text
+fn itxt_text(rest: &[u8]) -> Option<Vec<u8>>
+{
+	let compressed = *rest.first()? != 0;
+	let mut fields = rest.get(2..)?.splitn(3, |&c| c == 0);
+	let _language = fields.next()?;
+	let _translated = fields.next()?;
+	let text = fields.next()?;
+	if compressed {
+		zlib_decompress(text, MAX_TEXT_SIZE)
+	} else {
+		Some(text.to_vec())
+	}
+}
+
+/// Reads the keyword at the start of a text chunk (tEXt, zTXt or iTXt), without
+/// reading the rest of the chunk. Returns None if the keyword is not terminated.
+fn read_keyword<S: ByteSource>(contents: &mut S, offset: usize, length: usize) -> Option<Vec<u8>>
+{
+	let mut start = contents.read_at(offset, length.min(MAX_KEYWORD_SIZE))?;
+	let nul = start.iter().position(|&c| c == 0)?;
+	start.truncate(nul);
+	return Some(start);
+}
+
+/// Gets the EXIF data from a legacy text chunk (tEXt, zTXt or iTXt) whose
+/// keyword is "Raw profile type exif" or "Raw profile type APP1".
+/// Returns None for other keywords and for broken chunks.
+fn text_chunk_exif(chunk_type: &[u8], data: &[u8]) -> Option<Vec<u8>>
+{
+	let nul = data.iter().position(|&c| c == 0)?;
+	if !EXIF_KEYWORDS.contains(&&data[..nul]) {
+		return None;
+	}
+	let rest = &data[nul + 1..];
+
+	let text = match chunk_type {
+		b"tEXt" => rest.to_vec(),
+		b"zTXt" => {
+			// compression method must be 0 (zlib)
+			if *rest.first()? != 0 {
+				return None;
+			}
+			zlib_decompress(&rest[1..], MAX_TEXT_SIZE)?
+		},
+		_ => itxt_text(rest)?,
+	};
+
+	return decode_raw_profile(&text).map(strip_exif_prefix);
+}
+
+/// Find the EXIF data (a TIFF block) in a PNG image. It is found in the eXIf chunk,
+/// or else in a legacy "Raw profile type exif" text chunk. Chunks that don't
+/// carry EXIF data are skipped without being read, and only the keyword of
+/// other text chunks is read.
+///
+/// If `check_crc` is true, the CRC of the chunk is verified, and a mismatch
+/// is an error.
+pub fn find_exif_in_png<S: ByteSource>(contents: &mut S, check_crc: bool)
+					-> Result<Vec<u8>, ExifError>
+{
+	let mut offset: usize = 8;
+	let mut legacy: Option<Vec<u8>> = None;
+
+	while offset < contents.size() {
+		let header = match contents.read_at(offset, 8) {
+			Some(header) => header,
+			// truncated file, but a legacy chunk may have been found
+			None => break,
+		};
+
+		let length = read_u32(false, &header[0..4]) as usize;
+		let chunk_type = &header[4..8];
+		if length > 0x7fff_ffff {
+			return Err(ExifError::PngWithoutExif(format!("Invalid chunk length {}", length)));
+		}
+		offset += 8;
+
+		let is_exif = chunk_type == b"eXIf";
+		let is_text = chunk_type == b"tEXt" || chunk_type == b"zTXt" || chunk_type == b"iTXt";
+		let is_text = is_text && legacy.is_none() && length <= MAX_TEXT_SIZE &&
+			read_keyword(contents, offset, length).is_some_and(|k| EXIF_KEYWORDS.contains(&&k[..]));
+
+		if is_exif || is_text {
+			let data = match contents.read_at(offset, length) {
+				Some(data) => data,
+				None => return Err(ExifError::PngWithoutExif("PNG truncated in chunk data".to_string())),
+			};
+
+			let exif = if is_exif {
+				Some(strip_exif_prefix(data.clone()))
+			} else {
+				text_chunk_exif(chunk_type, &data)
+			};
+
+			if let Some(exif) = exif {
+				if check_crc {
+					let crc = match contents.read_at(offset + length, 4) {
+						Some(crc) => read_u32(false, &crc),
+						None => return Err(ExifError::PngWithoutExif("PNG truncated in chunk CRC".to_string())),
+					};
+					if crc != crc32(&[chunk_type, &data]) {
+						return Err(ExifError::PngWithoutExif(format!("CRC mismatch in {} chunk",
+							String::from_utf8_lossy(chunk_type))));
+					}
+				}
+
+				if is_exif {
+					return Ok(exif);
+				}
+				legacy = Some(exif);
+			}
+		}
+
+		if chunk_type == b"IEND" {
+			break;
+		}
+		offset = offset.saturating_add(length + 4);
+	}
+
+	match legacy {
+		Some(exif) => Ok(exif),
+		None => Err(ExifError::PngWithoutExif("No eXIf chunk found".to_string())),
+	}
+}
+
+/// Find the XMP packet of a PNG image, stored in the iTXt chunk whose keyword is
+/// "XML:com.adobe.xmp". Only the keyword of other text chunks is read.
+/// Returns None if there is no such chunk, or if it is broken.
+pub fn find_xmp_in_png<S: ByteSource>(contents: &mut S) -> Option<Vec<u8>>
+{
+	let mut offset: usize = 8;
+
+	while offset < contents.size() {
+		let header = contents.read_at(offset, 8)?;
+		let length = read_u32(false, &header[0..4]) as usize;
+		let chunk_type = &header[4..8];
+		offset += 8;
+
+		if chunk_type == b"iTXt" && length <= MAX_TEXT_SIZE &&
+				read_keyword(contents, offset, length).is_some_and(|k| k == XMP_KEYWORD) {
+			let data = contents.read_at(offset, length)?;
+			return itxt_text(&data[XMP_KEYWORD.len() + 1..]);
+		}
+
+		if chunk_type == b"IEND" {
+			break;
+		}
+		offset = offset.saturating_add(length).saturating_add(4);
+	}
+
+	return None;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// PNG image made of the given chunks, with their CRC
+	fn png(chunks: &[(&[u8], &[u8])]) -> Vec<u8>
+	{
+		let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+		for &(chunk_type, data) in chunks {
+			png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+			png.extend_from_slice(chunk_type);
+			png.extend_from_slice(data);
+			png.extend_from_slice(&crc32(&[chunk_type, data]).to_be_bytes());
+		}
+		return png;
+	}
+
+	/// Source that remembers the largest read
+	struct Recorder<'a> {
+		data: &'a [u8],
+		largest: usize,
+	}
+
+	impl<'a> ByteSource for Recorder<'a> {
+		fn size(&self) -> usize
+		{
+			self.data.len()
+		}
+
+		fn read_at(&mut self, offset: usize, length: usize) -> Option<Vec<u8>>
+		{
+			self.largest = self.largest.max(length);
+			self.data.read_at(offset, length)
+		}
+	}
+
+	const TIFF: &[u8] = b"MM\0\x2a\0\0\0\x08\0\0\0\0\0\0";
+
+	#[test]
+	fn exif_chunk() {
+		let mut prefixed = b"Exif\0\0".to_vec();
+		prefixed.extend_from_slice(TIFF);
+		for data in &[TIFF, &prefixed[..]] {
+			let image = png(&[(b"IHDR", &[0; 13]), (b"eXIf", data), (b"IEND", b"")]);
+			assert_eq!(find_exif_in_png(&mut &image[..], true).unwrap(), TIFF);
+
+			let mut broken = image.clone();
+			let crc = 8 + 25 + 8 + data.len();
+			broken[crc] ^= 1;
+			assert_eq!(find_exif_in_png(&mut &broken[..], false).unwrap(), TIFF);
+			assert!(find_exif_in_png(&mut &broken[..], true).is_err());
+		}
+	}
+
+	#[test]
+	fn raw_profile() {
+		let hex: String = TIFF.iter().map(|c| format!("{:02x}", c)).collect();
+		let text = format!("Raw profile type exif\0\nexif\n      {}\n{}\n", TIFF.len(), hex);
+		let comment = [b"Comment\0".to_vec(), vec![b'x'; 100_000]].concat();
+		let image = png(&[
+			(b"IHDR", &[0; 13]),
+			(b"tEXt", &comment),
+			(b"tEXt", text.as_bytes()),
+			(b"IEND", b""),
+		]);
+		let mut source = Recorder { data: &image, largest: 0 };
+		assert_eq!(find_exif_in_png(&mut source, true).unwrap(), TIFF);
+		// only the keyword of the comment is read
+		assert!(source.largest < 1000);
+
+		let image = png(&[(b"IHDR", &[0; 13]), (b"tEXt", &comment), (b"IEND", b"")]);
+		assert!(find_exif_in_png(&mut &image[..], false).is_err());
+	}
+
+	#[test]
+	fn xmp_chunk() {
+		let comment = [b"Comment\0\0\0\0\0".to_vec(), vec![b'x'; 100_000]].concat();
+		let xmp = b"XML:com.adobe.xmp\0\0\0\0\0<x:xmpmeta/>";
+		let image = png(&[
+			(b"IHDR", &[0; 13]),
+			(b"iTXt", &comment),
+			(b"iTXt", xmp),
+			(b"IEND", b""),
+		]);
+		let mut source = Recorder { data: &image, largest: 0 };
+		assert_eq!(find_xmp_in_png(&mut source).unwrap(), b"<x:xmpmeta/>");
+		assert!(source.largest < 1000);
+	}
+}
@@ -0,0 +1,179 @@
+use super::types::ExifError;
+use super::lowlevel::{read_u16, read_u32};
+use super::source::ByteSource;
+use super::irb::{split_image_resources, find_image_resource};
+
+/// Size of the PSD/PSB file header: signature, version, reserved bytes,
+/// channels, height, width, depth and color mode
+const HEADER_SIZE: usize = 26;
+
+/// Image resources that hold the EXIF data (a TIFF block), in order of preference
+static EXIF_RESOURCES: [u16; 2] = [0x0422, 0x0423];
+
+/// Image resource that holds the XMP packet
+const XMP_RESOURCE: u16 = 0x0424;
+
+/// Find the Image Resources section of a Photoshop image, which comes after the
+/// file header and the Color Mode Data section. PSB (version 2) differs from
+/// PSD (version 1) in the 8-byte lengths of the sections that follow, from
+/// Layer and Mask Information on, which are never reached here.
+/// Returns the contents of the section, a Photoshop Image Resource Block.
+pub fn find_photoshop_irb_in_psd<S: ByteSource>(contents: &mut S) -> Result<Vec<u8>, ExifError>
+{
+	let header = match contents.read_at(0, HEADER_SIZE + 4) {
+		Some(header) => header,
+		None => return Err(ExifError::PsdWithoutExif("PSD header truncated".to_string())),
+	};
+	match read_u16(false, &header[4..6]) {
+		1 | 2 => {},
+		version => return Err(ExifError::PsdWithoutExif(format!("Unknown PSD version {}", version))),
+	}
+
+	let color_mode_size = read_u32(false, &header[HEADER_SIZE..HEADER_SIZE + 4]) as usize;
+	let offset = (HEADER_SIZE + 4).saturating_add(color_mode_size);
+
+	let size = match contents.read_at(offset, 4) {
+		Some(size) => read_u32(false, &size) as usize,
+		None => return Err(ExifError::PsdWithoutExif("PSD truncated in color mode data".to_string())),
+	};
+
+	match contents.read_at(offset + 4, size) {
+		Some(irb) => Ok(irb),
+		None => Err(ExifError::PsdWithoutExif("PSD truncated in image resources".to_string())),
+	}
+}
+
+/// Find the EXIF data (a TIFF block) in a Photoshop image, stored in image
+/// resource 0x0422, or else 0x0423
+pub fn find_exif_in_psd<S: ByteSource>(contents: &mut S) -> Result<Vec<u8>, ExifError>
+{
+	let irb = find_photoshop_irb_in_psd(contents)?;
+	let resources = split_image_resources(&irb)
+		.map_err(|e| ExifError::PsdWithoutExif(e.to_string()))?;
+
+	for &id in EXIF_RESOURCES.iter() {
+		if let Some(tiff) = find_image_resource(&resources, id) {
+			return Ok(tiff.to_vec());
+		}
+	}
+
+	return Err(ExifError::PsdWithoutExif("No EXIF resource found".to_string()));
+}
+
+/// Find the XMP packet of a Photoshop image, stored in image resource 0x0424.
+/// Returns None if there is no such resource.
+pub fn find_xmp_in_psd<S: ByteSource>(contents: &mut S) -> Option<Vec<u8>>
+{
+	let irb = find_photoshop_irb_in_psd(contents).ok()?;
+	let resources = split_image_resources(&irb).ok()?;
+	return find_image_resource(&resources, XMP_RESOURCE).map(|xmp| xmp.to_vec());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use types::{ExifTag, XmpValue};
+	use testutil::*;
+
+	static XMP: &[u8] = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\
+		<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+		<rdf:Description xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmp:CreatorTool=\"Photoshop\"/>\
+		</rdf:RDF></x:xmpmeta>";
+
+	/// PSD (version 1) or PSB (version 2) image with the given image resources,
+	/// and an empty Layer and Mask Information section
+	fn psd(version: u16, resources: &[Vec<u8>]) -> Vec<u8>
+	{
+		let mut psd = b"8BPS".to_vec();
+		psd.extend_from_slice(&version.to_be_bytes());
+		psd.extend_from_slice(&[0; 6]);
+		psd.extend_from_slice(&3u16.to_be_bytes());
+		psd.extend_from_slice(&[0, 0, 0, 2, 0, 0, 0, 2, 0, 8, 0, 3]);
+		// color mode data, only used by indexed and duotone images
+		psd.extend_from_slice(&[0, 0, 0, 4, 1, 2, 3, 4]);
+
+		let irb: Vec<u8> = resources.concat();
+		psd.extend_from_slice(&(irb.len() as u32).to_be_bytes());
+		psd.extend_from_slice(&irb);
+
+		psd.extend_from_slice(if version == 2 {&[0; 8]} else {&[0; 4]});
+		// raw image data
+		psd.extend_from_slice(&[0, 0]);
+		psd.extend_from_slice(&[0x80; 12]);
+		return psd;
+	}
+
+	fn error<T>(r: Result<T, ExifError>) -> String
+	{
+		match r {
+			Err(ExifError::PsdWithoutExif(message)) => message,
+			Err(e) => panic!("unexpected error {}", e),
+			Ok(_) => panic!("no error"),
+		}
+	}
+
+	#[test]
+	fn metadata() {
+		for &version in &[1, 2] {
+			let image = psd(version, &[
+				image_resource(b"8BIM", 0x03ed, b"", &[0; 16]),
+				image_resource(b"8BIM", 0x0404, b"", b"\x1c\x02\x19\x00\x05psd k"),
+				image_resource(b"8BIM", 0x0422, b"", &sample_exif(false)),
+				image_resource(b"8BIM", 0x0424, b"", XMP),
+			]);
+
+			let d = ::parse_buffer(&image).unwrap();
+			assert_eq!(d.mime, "image/vnd.adobe.photoshop");
+			let make = d.entries.iter().find(|e| e.tag == ExifTag::Make).unwrap();
+			assert_eq!(make.value_more_readable, "Canon");
+			assert_eq!(d.xmp.len(), 1);
+			assert_eq!((&d.xmp[0].name[..], &d.xmp[0].value), ("CreatorTool", &XmpValue::Simple("Photoshop".to_string())));
+			assert_eq!(d.iptc.len(), 1);
+			assert_eq!(d.iptc[0].value, "psd k");
+
+			let resources = ::read_image_resources(&image).unwrap();
+			let ids: Vec<u16> = resources.iter().map(|r| r.id).collect();
+			assert_eq!(ids, vec![0x03ed, 0x0404, 0x0422, 0x0424]);
+		}
+	}
+
+	#[test]
+	fn exif_resources() {
+		// 0x0422 is preferred over 0x0423, whatever the order
+		let image = psd(1, &[
+			image_resource(b"8BIM", 0x0423, b"", b"second"),
+			image_resource(b"8BIM", 0x0422, b"", b"first"),
+		]);
+		assert_eq!(find_exif_in_psd(&mut &image[..]).unwrap(), b"first");
+
+		let image = psd(1, &[image_resource(b"8BIM", 0x0423, b"", b"second")]);
+		assert_eq!(find_exif_in_psd(&mut &image[..]).unwrap(), b"second");
+
+		let image = psd(1, &[image_resource(b"8BIM", 0x0424, b"", XMP)]);
+		assert_eq!(error(find_exif_in_psd(&mut &image[..])), "No EXIF resource found");
+		assert_eq!(find_xmp_in_psd(&mut &image[..]).unwrap(), XMP);
+		assert!(matches!(::parse_buffer(&image), Err(ExifError::PsdWithoutExif(_))));
+	}
+
+	#[test]
+	fn broken_sections() {
+		let image = psd(1, &[image_resource(b"8BIM", 0x0422, b"", &sample_exif(true))]);
+		let irb_start = HEADER_SIZE + 4 + 4 + 4;
+
+		assert_eq!(error(find_exif_in_psd(&mut &image[..HEADER_SIZE])), "PSD header truncated");
+		// color mode data past EOF
+		let mut broken = image.clone();
+		broken[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&0x1000_0000u32.to_be_bytes());
+		assert_eq!(error(find_exif_in_psd(&mut &broken[..])), "PSD truncated in color mode data");
+		// image resources past EOF
+		assert_eq!(error(find_exif_in_psd(&mut &image[..irb_start + 10])), "PSD truncated in image resources");
+		// unknown version
+		let mut broken = image.clone();
+		broken[5] = 3;
+		assert_eq!(error(find_exif_in_psd(&mut &broken[..])), "Unknown PSD version 3");
+		// malformed resource
+		let mut broken = image.clone();
+		broken[irb_start + 8..irb_start + 12].copy_from_slice(&0x1000u32.to_be_bytes());
+		assert!(error(find_exif_in_psd(&mut &broken[..])).contains("truncated"));
+	}
+}
@@ -0,0 +1,98 @@
+use super::types::ExifError;
+use super::lowlevel::read_u32;
+use super::source::*;
+use super::jpeg::{find_embedded_tiff_in_jpeg, find_xmp_in_jpeg};
+
+/// Find the JPEG preview embedded in a Fujifilm RAF raw image, that the RAF
+/// header points to. Returns offset and size of the JPEG.
+fn find_jpeg_in_raf<S: ByteSource>(contents: &mut S) -> Result<(usize, usize), ExifError>
+{
+	// Magic, format version, camera ID, camera name and directory version
+	// come first. Offset and length of the JPEG are at 84 and 88.
+	let header = match contents.read_at(0, 92) {
+		Some(header) => header,
+		None => return Err(ExifError::RafWithoutExif("RAF header truncated".to_string())),
+	};
+
+	let jpeg_offset = read_u32(false, &header[84..88]) as usize;
+	let jpeg_length = read_u32(false, &header[88..92]) as usize;
+	if jpeg_offset.saturating_add(jpeg_length) > contents.size() {
+		return Err(ExifError::RafWithoutExif("JPEG preview goes beyond EOF".to_string()));
+	}
+
+	match contents.read_at(jpeg_offset, 2) {
+		Some(ref soi) if jpeg_length >= 2 && soi[..] == [0xff, 0xd8] => Ok((jpeg_offset, jpeg_length)),
+		_ => Err(ExifError::RafWithoutExif("JPEG preview not found".to_string())),
+	}
+}
+
+/// Find the EXIF data (a TIFF block) in a Fujifilm RAF raw image. The RAF header
+/// points to an embedded JPEG preview, whose APP1 segment holds the EXIF data.
+/// Returns offset and size of the TIFF block, relative to the RAF file.
+pub fn find_embedded_tiff_in_raf<S: ByteSource>(contents: &mut S)
+					-> Result<(usize, usize), ExifError>
+{
+	let (jpeg_offset, jpeg_length) = find_jpeg_in_raf(contents)?;
+	let mut jpeg = SubSource::new(contents, jpeg_offset, jpeg_length);
+	let (offset, size) = find_embedded_tiff_in_jpeg(&mut jpeg)?;
+	return Ok((jpeg_offset + offset, size));
+}
+
+/// Find the XMP packet of a Fujifilm RAF raw image, stored in the APP1 segment
+/// of the embedded JPEG preview. Returns None if there is no packet.
+pub fn find_xmp_in_raf<S: ByteSource>(contents: &mut S) -> Option<Vec<u8>>
+{
+	let (jpeg_offset, jpeg_length) = find_jpeg_in_raf(contents).ok()?;
+	let mut jpeg = SubSource::new(contents, jpeg_offset, jpeg_length);
+	return find_xmp_in_jpeg(&mut jpeg);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testutil::*;
+
+	/// RAF image with the given JPEG preview, and the offset and length of the
+	/// preview that the header gives
+	fn raf(jpeg: &[u8], offset: u32, length: u32) -> Vec<u8>
+	{
+		let mut raf = b"FUJIFILMCCD-RAW 0201FF383501".to_vec();
+		let mut name = b"X-T5".to_vec();
+		name.resize(32, 0);
+		raf.extend(name);
+		raf.extend_from_slice(b"0100");
+		raf.resize(84, 0);
+		raf.extend_from_slice(&offset.to_be_bytes());
+		raf.extend_from_slice(&length.to_be_bytes());
+		raf.resize(160, 0);
+		raf.extend_from_slice(jpeg);
+		raf.extend_from_slice(b"CFA data");
+		return raf;
+	}
+
+	#[test]
+	fn jpeg_preview() {
+		let tiff = sample_exif(false);
+		let jpeg = jpeg_with_exif(&tiff);
+		let image = raf(&jpeg, 160, jpeg.len() as u32);
+		let (offset, size) = find_embedded_tiff_in_raf(&mut &image[..]).unwrap();
+		// Exif segment after SOI and APP0
+		assert_eq!(offset, 160 + 2 + 18 + 10);
+		assert_eq!(&image[offset..offset + size], &tiff[..]);
+
+		let d = ::parse_buffer(&image).unwrap();
+		assert_eq!(d.mime, "image/x-fuji-raf");
+		assert!(d.entries.iter().any(|e| e.tag == ::ExifTag::Make));
+	}
+
+	#[test]
+	fn broken_header() {
+		let jpeg = jpeg_with_exif(&sample_exif(false));
+		let past_eof = raf(&jpeg, 160, jpeg.len() as u32 + 100);
+		let not_jpeg = raf(&jpeg, 150, jpeg.len() as u32);
+		let truncated = &raf(&jpeg, 160, jpeg.len() as u32)[..90];
+		for image in &[&past_eof[..], &not_jpeg[..], truncated] {
+			assert!(find_embedded_tiff_in_raf(&mut &image[..]).is_err());
+		}
+	}
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use super::lowlevel::slice_at;
+
+/// Random access to a block of bytes, e.g. an image file or the TIFF block
+/// within it. Parsers ask only for the parts they need, so the block does not
+/// have to be loaded whole into memory.
+pub trait ByteSource {
+	/// Total length of the block
+	fn size(&self) -> usize;
+
+	/// Reads `length` bytes at `offset`. Returns None if the range goes
+	/// beyond the end of the block, or could not be read.
+	fn read_at(&mut self, offset: usize, length: usize) -> Option<Vec<u8>>;
+}
+
+impl ByteSource for &[u8] {
+	fn size(&self) -> usize
+	{
+		self.len()
+	}
+
+	fn read_at(&mut self, offset: usize, length: usize) -> Option<Vec<u8>>
+	{
+		slice_at(self, offset, length).map(|s| s.to_vec())
+	}
+}
+
+/// Block of bytes found at a given position of another source, e.g. a TIFF
+/// block embedded in a container format
+pub struct SubSource<'a, S: 'a + ByteSource> {
+	source: &'a mut S,
+	base: usize,
+	size: usize,
+}
+
+impl<'a, S: ByteSource> SubSource<'a, S> {
+	/// Block of `size` bytes, starting at `base` in the source
+	pub fn new(source: &'a mut S, base: usize, size: usize) -> SubSource<'a, S>
+	{
+		SubSource { source, base, size }
+	}
+}
+
+impl<'a, S: ByteSource> ByteSource for SubSource<'a, S> {
+	fn size(&self) -> usize
+	{
+		self.size
+	}
+
+	fn read_at(&mut self, offset: usize, length: usize) -> Option<Vec<u8>>
+	{
+		let end = offset.checked_add(length)?;
+		if end > self.size {
+			return None;
+		}
+		self.source.read_at(self.base.checked_add(offset)?, length)
+	}
+}
+
+/// Largest read kept in the cache of `ReaderSource`: enough for box, chunk and
+/// marker headers, JPEG segment signatures and PNG keywords
+const MAX_CACHED_READ: usize = 128;
+
+/// Number of reads kept in the cache of `ReaderSource`
+const MAX_CACHED_READS: usize = 4096;
+
+/// Block of bytes found at a given position of a seekable stream, e.g. a file.
+///
+/// The EXIF data, the XMP packet and the IPTC record are looked for one after
+/// the other, and each search walks the same headers of the container. Small
+/// reads are cached, so the stream is only read once for them.
+pub struct ReaderSource<'a, R: 'a + Read + Seek> {
+	reader: &'a mut R,
+	base: u64,
+	size: usize,
+	cache: HashMap<(usize, usize), Vec<u8>>,
+	/// First I/O error found. Reads just fail, so the error must be checked
+	/// after parsing in order to tell a truncated image from a broken stream.
+	pub error: Option<io::Error>,
+}
+
+impl<'a, R: Read + Seek> ReaderSource<'a, R> {
+	/// Block of `size` bytes, starting at `base` in the stream
+	pub fn new(reader: &'a mut R, base: u64, size: usize) -> ReaderSource<'a, R>
+	{
+		ReaderSource { reader, base, size, cache: HashMap::new(), error: None }
+	}
+
+	/// Returns the first I/O error found, if any
+	pub fn check(&mut self) -> Result<(), io::Error>
+	{
+		match self.error.take() {
+			Some(e) => Err(e),
+			None => Ok(()),
+		}
+	}
+}
+
+impl<'a, R: Read + Seek> ByteSource for ReaderSource<'a, R> {
+	fn size(&self) -> usize
+	{
+		self.size
+	}
+
+	fn read_at(&mut self, offset: usize, length: usize) -> Option<Vec<u8>>
+	{
+		let end = offset.checked_add(length)?;
+		if end > self.size {
+			return None;
+		}
+		if let Some(cached) = self.cache.get(&(offset, length)) {
+			return Some(cached.clone());
+		}
+
+		let mut buf = vec![0u8; length];
+		let r = self.reader.seek(SeekFrom::Start(self.base + offset as u64))
+			.and_then(|_| self.reader.read_exact(&mut buf));
+
+		if let Err(e) = r {
+			if self.error.is_none() {
+				self.error = Some(e);
+			}
+			return None;
+		}
+
+		if length <= MAX_CACHED_READ && self.cache.len() < MAX_CACHED_READS {
+			self.cache.insert((offset, length), buf.clone());
+		}
+		return Some(buf);
+	}
+}
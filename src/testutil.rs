@@ -0,0 +1,221 @@
+//! Builders of small images for the unit tests
+
+/// IFD entry to be written by `Tiff::ifd`
+pub struct Entry {
+	pub tag: u16,
+	pub format: u16,
+	/// Integers, or bytes of ASCII and UNDEFINED entries. Rationals take
+	/// two values each, numerator and denominator.
+	pub values: Vec<u64>,
+}
+
+/// Entry of a numeric format, e.g. 3 (SHORT) or 4 (LONG)
+pub fn entry(tag: u16, format: u16, values: &[u64]) -> Entry
+{
+	Entry { tag, format, values: values.to_vec() }
+}
+
+/// ASCII entry, with the terminating NUL added
+pub fn ascii(tag: u16, text: &str) -> Entry
+{
+	let mut values: Vec<u64> = text.bytes().map(u64::from).collect();
+	values.push(0);
+	Entry { tag, format: 2, values }
+}
+
+/// UNDEFINED entry
+pub fn undefined(tag: u16, data: &[u8]) -> Entry
+{
+	Entry { tag, format: 7, values: data.iter().map(|&c| u64::from(c)).collect() }
+}
+
+/// Size of each value of a format
+fn value_size(format: u16) -> usize
+{
+	match format {
+		3 | 8 => 2,
+		4 | 5 | 9 | 10 | 11 | 13 => 4,
+		12 | 16 | 17 | 18 => 8,
+		_ => 1,
+	}
+}
+
+/// TIFF block written piece by piece. Each IFD or blob is appended and its offset
+/// returned, so IFDs are written after the ones they point to.
+pub struct Tiff {
+	pub le: bool,
+	pub bigtiff: bool,
+	pub data: Vec<u8>,
+}
+
+impl Tiff {
+	/// Classic TIFF, with the IFD0 offset left to `finish`
+	pub fn new(le: bool) -> Tiff
+	{
+		let mut tiff = Tiff { le, bigtiff: false, data: Vec::new() };
+		tiff.data.extend_from_slice(if le {b"II"} else {b"MM"});
+		tiff.put(42, 2);
+		tiff.put(0, 4);
+		return tiff;
+	}
+
+	/// BigTIFF, with the IFD0 offset left to `finish`
+	pub fn new_bigtiff(le: bool) -> Tiff
+	{
+		let mut tiff = Tiff { le, bigtiff: true, data: Vec::new() };
+		tiff.data.extend_from_slice(if le {b"II"} else {b"MM"});
+		tiff.put(43, 2);
+		tiff.put(8, 2);
+		tiff.put(0, 2);
+		tiff.put(0, 8);
+		return tiff;
+	}
+
+	/// Appends an integer of `size` bytes
+	fn put(&mut self, value: u64, size: usize)
+	{
+		let bytes = if self.le {value.to_le_bytes()} else {value.to_be_bytes()};
+		if self.le {
+			self.data.extend_from_slice(&bytes[..size]);
+		} else {
+			self.data.extend_from_slice(&bytes[8 - size..]);
+		}
+	}
+
+	/// Appends raw bytes, e.g. a thumbnail, and returns their offset
+	pub fn blob(&mut self, data: &[u8]) -> u64
+	{
+		let offset = self.data.len() as u64;
+		self.data.extend_from_slice(data);
+		return offset;
+	}
+
+	/// Appends an IFD, followed by the data of its entries that does not fit
+	/// in the IFD, and returns its offset
+	pub fn ifd(&mut self, entries: &[Entry], next: u64) -> u64
+	{
+		let offset = self.data.len();
+		let (count_size, entry_size, offset_size) = if self.bigtiff {(8, 20, 8)} else {(2, 12, 4)};
+		let mut data_offset = offset + count_size + entries.len() * entry_size + offset_size;
+		let mut outside: Vec<u8> = Vec::new();
+
+		self.put(entries.len() as u64, count_size);
+		for entry in entries {
+			let size = value_size(entry.format);
+			let count = if entry.format == 5 || entry.format == 10 {entry.values.len() / 2} else {entry.values.len()};
+			self.put(u64::from(entry.tag), 2);
+			self.put(u64::from(entry.format), 2);
+			self.put(count as u64, offset_size);
+
+			let mut value = Tiff { le: self.le, bigtiff: self.bigtiff, data: Vec::new() };
+			for &v in &entry.values {
+				value.put(v, size);
+			}
+			if value.data.len() <= offset_size {
+				value.data.resize(offset_size, 0);
+				self.data.extend_from_slice(&value.data);
+			} else {
+				self.put(data_offset as u64, offset_size);
+				data_offset += value.data.len();
+				outside.extend_from_slice(&value.data);
+			}
+		}
+		self.put(next, offset_size);
+		self.data.extend_from_slice(&outside);
+
+		return offset as u64;
+	}
+
+	/// Sets the IFD0 offset and returns the block
+	pub fn finish(mut self, ifd0: u64) -> Vec<u8>
+	{
+		let (at, size) = if self.bigtiff {(8, 8)} else {(4, 4)};
+		let mut header = Tiff { le: self.le, bigtiff: self.bigtiff, data: Vec::new() };
+		header.put(ifd0, size);
+		self.data[at..at + size].copy_from_slice(&header.data);
+		return self.data;
+	}
+}
+
+/// JPEG image with the given TIFF block in its Exif APP1 segment, and a few
+/// bytes of compressed data
+pub fn jpeg_with_exif(tiff: &[u8]) -> Vec<u8>
+{
+	let mut jpeg = vec![0xff, 0xd8];
+	jpeg.extend_from_slice(&[0xff, 0xe0, 0, 16]);
+	jpeg.extend_from_slice(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+	jpeg.extend_from_slice(&[0xff, 0xe1]);
+	jpeg.extend_from_slice(&(tiff.len() as u16 + 8).to_be_bytes());
+	jpeg.extend_from_slice(b"Exif\0\0");
+	jpeg.extend_from_slice(tiff);
+	jpeg.extend_from_slice(&[0xff, 0xda, 0, 2, 0x12, 0x34, 0xff, 0xd9]);
+	return jpeg;
+}
+
+/// TIFF block of a typical camera JPEG: IFD0 with Make, Model and Orientation,
+/// Exif IFD with ExposureTime and the Interoperability IFD, GPS IFD, and IFD1
+/// that describes a JPEG thumbnail
+pub fn sample_exif(le: bool) -> Vec<u8>
+{
+	let mut tiff = Tiff::new(le);
+	let thumbnail = tiff.blob(b"\xff\xd8thumbnail\xff\xd9");
+	let ifd1 = tiff.ifd(&[
+		entry(0x0103, 3, &[6]),
+		entry(0x0201, 4, &[thumbnail]),
+		entry(0x0202, 4, &[13]),
+	], 0);
+	let interop = tiff.ifd(&[ascii(0x0001, "R98"), undefined(0x0002, b"0100")], 0);
+	let exif = tiff.ifd(&[
+		entry(0x829a, 5, &[1, 250]),
+		entry(0x8827, 3, &[200]),
+		entry(0xa005, 4, &[interop]),
+	], 0);
+	let gps = tiff.ifd(&[
+		entry(0x0000, 1, &[2, 3, 0, 0]),
+		ascii(0x0001, "N"),
+		entry(0x0002, 5, &[48, 1, 51, 1, 0, 1]),
+	], 0);
+	let ifd0 = tiff.ifd(&[
+		ascii(0x010f, "Canon"),
+		ascii(0x0110, "Canon EOS 5D"),
+		entry(0x0112, 3, &[6]),
+		entry(0x8769, 4, &[exif]),
+		entry(0x8825, 4, &[gps]),
+	], ifd1);
+	return tiff.finish(ifd0);
+}
+
+/// Box of the ISO base media file format
+pub fn bmff_box(box_type: &[u8], data: &[u8]) -> Vec<u8>
+{
+	let mut b = (data.len() as u32 + 8).to_be_bytes().to_vec();
+	b.extend_from_slice(box_type);
+	b.extend_from_slice(data);
+	return b;
+}
+
+/// Box with version and flags (FullBox), flags being zero
+pub fn full_box(box_type: &[u8], version: u8, data: &[u8]) -> Vec<u8>
+{
+	let mut full = vec![version, 0, 0, 0];
+	full.extend_from_slice(data);
+	return bmff_box(box_type, &full);
+}
+
+/// Photoshop image resource, with its name and data padded to even sizes
+pub fn image_resource(signature: &[u8], id: u16, name: &[u8], data: &[u8]) -> Vec<u8>
+{
+	let mut raw = signature.to_vec();
+	raw.extend_from_slice(&id.to_be_bytes());
+	raw.push(name.len() as u8);
+	raw.extend_from_slice(name);
+	if name.len() & 1 == 0 {
+		raw.push(0);
+	}
+	raw.extend_from_slice(&(data.len() as u32).to_be_bytes());
+	raw.extend_from_slice(data);
+	if data.len() & 1 == 1 {
+		raw.push(0);
+	}
+	return raw;
+}
@@ -1,17 +1,102 @@
+use std::collections::HashSet;
 use super::types::*;
 use super::types_impl::*;
 use super::lowlevel::*;
 use super::ifdformat::*;
-use super::debug::*;
 use super::exif::*;
 use super::exifpost::*;
+use super::source::ByteSource;
+use super::xmp::xmp_from_entries;
+use super::iptc::iptc_from_entries;
 
-type InExifResult = Result<(), ExifError>;
+/// Offset of the next IFD in chain, or 0 if there is none
+type NextIfdResult = Result<usize, ExifError>;
+
+/// Maximum number of IFDs parsed in a TIFF block, counting every kind of IFD
+const MAX_IFDS: usize = 1024;
+
+/// Maximum number of IFD entries parsed in a TIFF block
+const MAX_ENTRIES: usize = 65536;
+
+/// What is left of the IFDs and IFD entries that may be parsed in a TIFF block.
+/// Real-world files are far below the limits, which only stop hostile files
+/// (e.g. thousands of IFDs that share their entries) from taking too long.
+struct Budget {
+	ifds: usize,
+	entries: usize,
+}
+
+impl Budget {
+	fn new() -> Budget
+	{
+		Budget { ifds: MAX_IFDS, entries: MAX_ENTRIES }
+	}
+}
+
+/// Byte order and IFD layout of a TIFF block. Classic TIFF has 2-byte entry
+/// counts, 12-byte entries and 4-byte offsets. BigTIFF has 8-byte entry counts,
+/// 20-byte entries (with 8-byte value counts) and 8-byte offsets.
+#[derive(Clone, Copy, Debug)]
+pub struct TiffLayout {
+	pub le: bool,
+	pub bigtiff: bool,
+}
+
+impl TiffLayout {
+	/// Size of the entry count that starts an IFD
+	fn count_size(&self) -> usize
+	{
+		if self.bigtiff {8} else {2}
+	}
+
+	/// Size of an IFD entry
+	fn entry_size(&self) -> usize
+	{
+		if self.bigtiff {20} else {12}
+	}
+
+	/// Size of an offset, and of the data area of an IFD entry
+	fn offset_size(&self) -> usize
+	{
+		if self.bigtiff {8} else {4}
+	}
+
+	/// Reads an offset, or the count of an IFD entry
+	fn read_offset(&self, raw: &[u8]) -> usize
+	{
+		if self.bigtiff {
+			read_u64(self.le, raw) as usize
+		} else {
+			read_u32(self.le, raw) as usize
+		}
+	}
+}
+
+/// Tags that the TIFF specification allows to be either SHORT or LONG: ImageWidth,
+/// ImageLength, StripOffsets, RowsPerStrip and StripByteCounts. They are known as
+/// LONG, and a SHORT value is widened without warning. A SHORT value of any other
+/// LONG tag has the wrong format.
+const SHORT_OR_LONG_TAGS: [u16; 5] = [0x0100, 0x0101, 0x0111, 0x0116, 0x0117];
+
+/// Tags that point to IFDs: SubIFDs, ExifOffset, GPSOffset and InteropOffset.
+/// They are known as LONG, and may also have the IFD format.
+const IFD_POINTER_TAGS: [u16; 4] = [0x014a, 0x8769, 0x8825, 0xa005];
+
+/// Tags that hold offsets or sizes in the file: StripOffsets, StripByteCounts,
+/// SubIFDs, JPEGInterchangeFormat, JPEGInterchangeFormatLength and the pointers
+/// to private IFDs. BigTIFF allows LONG8 (or IFD8 for the pointers) in these
+/// fields, and the value is narrowed to LONG without warning. A LONG8 value of
+/// any other LONG tag has the wrong format.
+const LONG8_TAGS: [u16; 8] = [0x0111, 0x0117, 0x014a, 0x0201, 0x0202, 0x8769, 0x8825, 0xa005];
 
 /// Parse of raw IFD entry into EXIF data, if it is of a known type, and returns
 /// an ExifEntry object. If the tag is unknown, the enumeration is set to UnknownToMe,
 /// but the raw information of tag is still available in the ifd member.
-pub fn parse_exif_entry(f: &IfdEntry) -> ExifEntry
+///
+/// Problems are reported in `warnings`. The `offset` of the IFD entry is
+/// only used to fill in the warnings.
+pub fn parse_exif_entry(f: &IfdEntry, offset: usize, options: &ParseOptions,
+			warnings: &mut Vec<ExifWarning>) -> ExifEntry
 {
 	let value = tag_value_new(f);
 
@@ -19,42 +104,66 @@ pub fn parse_exif_entry(f: &IfdEntry) -> ExifEntry
 			namespace: f.namespace,
 			ifd: f.clone(),
 			tag: ExifTag::UnknownToMe,
+			ifd_kind: f.ifd_kind,
 			value: value.clone(),
 			unit: "Unknown".to_string(),
 			value_more_readable: format!("{}", value),
 			};
 
-	let (tag, unit, format, min_count, max_count, more_readable) = tag_to_exif(f.tag);
+	let info = tag_to_exif(f.ifd_kind, f.tag);
+	// the tables are checked by a unit test
+	debug_assert!(tag_info_is_consistent(f.tag, &info), "Internal error {:x}", f.tag);
+	let (tag, unit, format, min_count, max_count, more_readable) = info;
 
 	if tag == ExifTag::UnknownToMe {
 		// Unknown EXIF tag type
 		return e;
 	}
 
-	// Internal assert:
-	// 1) tag must match enum
-	// 2) all types except Ascii, Undefined, Unknown must have definite length
-	// 3) Str type must not have a definite length
-	if (((tag as u32) & 0xffff) as u16) != f.tag ||
-		(min_count == -1 && (format != IfdFormat::Ascii &&
-				format != IfdFormat::Undefined &&
-				format != IfdFormat::Unknown)) ||
-		(min_count != -1 && format == IfdFormat::Ascii) {
-		panic!("Internal error {:x}", f.tag);
-	}
-
-	if format != f.format {
-		warning(&format!("EXIF tag {:x} {} ({}), expected format {} ({:?}), found {} ({:?})",
-			f.tag, f.tag, tag, format as u8, format, f.format as u8, f.format));
-		return e;
+	if format == IfdFormat::U32 && f.format == IfdFormat::U16 && SHORT_OR_LONG_TAGS.contains(&f.tag) {
+		// TIFF allows SHORT in these fields, but the value is always given as LONG
+		if let TagValue::U16(ref v) = value {
+			e.value = TagValue::U32(v.iter().map(|&n| u32::from(n)).collect());
+		}
+	} else if format == IfdFormat::U32 && f.format == IfdFormat::Ifd && IFD_POINTER_TAGS.contains(&f.tag) {
+		// IFD offsets are LONGs, value is already U32
+	} else if format != f.format {
+		// BigTIFF writers use LONG8 and IFD8 for the offsets
+		let long8 = format == IfdFormat::U32 && LONG8_TAGS.contains(&f.tag) &&
+			(f.format == IfdFormat::U64 || f.format == IfdFormat::Ifd8);
+		let coerced = if options.lenient || long8 {tag_value_coerce(&value, format)} else {None};
+		let coerced = match coerced {
+			Some(coerced) => coerced,
+			None => {
+				warnings.push(ExifWarning {
+					tag: f.tag,
+					ifd_kind: f.ifd_kind,
+					offset,
+					kind: ExifWarningKind::WrongFormat(format, f.format),
+				});
+				return e;
+			}
+		};
+		if !long8 {
+			warnings.push(ExifWarning {
+				tag: f.tag,
+				ifd_kind: f.ifd_kind,
+				offset,
+				kind: ExifWarningKind::FormatCoerced(format, f.format),
+			});
+		}
+		e.value = coerced;
 	}
 
 	if min_count != -1 &&
 			((f.count as i32) < min_count ||
 			(f.count as i32) > max_count) {
-		warning(&format!("EXIF tag {:x} {} ({:?}), format {}, expected count {}..{} found {}",
-			f.tag, f.tag, tag, format as u8, min_count,
-			max_count, f.count));
+		warnings.push(ExifWarning {
+			tag: f.tag,
+			ifd_kind: f.ifd_kind,
+			offset,
+			kind: ExifWarningKind::CountOutOfRange(min_count, max_count, f.count),
+		});
 		return e;
 	}
 
@@ -66,142 +175,960 @@ pub fn parse_exif_entry(f: &IfdEntry) -> ExifEntry
 }
 
 /// Superficial parse of IFD that can't fail
-pub fn parse_ifd(subifd: bool, le: bool, count: u16, contents: &[u8]) -> (Vec<IfdEntry>, usize)
+pub fn parse_ifd(kind: IfdKind, subifd: bool, layout: TiffLayout, count: usize, contents: &[u8]) -> (Vec<IfdEntry>, usize)
 {
+	let le = layout.le;
 	let mut entries: Vec<IfdEntry> = Vec::new();
 
 	for i in 0..count {
 		// println!("Parsing IFD entry {}", i);
-		let mut offset = (i as usize) * 12;
+		let mut offset = i * layout.entry_size();
 		let tag = read_u16(le, &contents[offset..offset + 2]);
 		offset += 2;
 		let format = read_u16(le, &contents[offset..offset + 2]);
 		offset += 2;
-		let count = read_u32(le, &contents[offset..offset + 4]);
-		offset += 4;
-		let data = &contents[offset..offset + 4];
+		// BigTIFF counts are 64-bit, but no sane count goes beyond 32 bits
+		let count = layout.read_offset(&contents[offset..offset + layout.offset_size()]);
+		let count = count.min(u32::MAX as usize) as u32;
+		offset += layout.offset_size();
+		let data = &contents[offset..offset + layout.offset_size()];
 		let data = data.to_vec();
 
 		let entry = IfdEntry{namespace: Namespace::Standard,
-					tag, format: ifdformat_new(format),
+					ifd_kind: kind, tag, format: ifdformat_new(format),
 					count, ifd_data: data, le,
 					ext_data: Vec::new(), data: Vec::new()};
 		entries.push(entry);
 	}
 
-	let next_ifd = if subifd {0} else {read_u32(le, &contents[count as usize * 12..]) as usize};
+	let next_ifd = if subifd {0} else {layout.read_offset(&contents[count * layout.entry_size()..])};
 
 	return (entries, next_ifd);
 }
 
-/// Deep parse of IFD that grabs EXIF data from IFD0, SubIFD and GPS IFD
-fn parse_exif_ifd(le: bool, contents: &[u8], ioffset: usize,
-				exif_entries: &mut Vec<ExifEntry>) -> InExifResult
+/// Deep parse of IFD that grabs EXIF data from IFD0, SubIFD and GPS IFD.
+/// Returns the offset of the next IFD in chain.
+///
+/// Fails if the budget of IFDs is exhausted. If only the budget of entries
+/// is, the IFD is cut short and a warning is added.
+#[allow(clippy::too_many_arguments)]
+fn parse_exif_ifd<S: ByteSource>(kind: IfdKind, layout: TiffLayout, contents: &mut S, ioffset: usize,
+				exif_entries: &mut Vec<ExifEntry>, options: &ParseOptions,
+				warnings: &mut Vec<ExifWarning>, budget: &mut Budget) -> NextIfdResult
 {
 	let mut offset = ioffset;
 
-	// println!("Offset is {}", offset);
-	if contents.len() < (offset + 2) {
-		return Err(ExifError::ExifIfdTruncated("Truncated at dir entry count".to_string()))
+	if budget.ifds == 0 {
+		return Err(ExifError::ExifIfdTruncated("Too many IFDs".to_string()));
 	}
+	budget.ifds -= 1;
 
-	let count = read_u16(le, &contents[offset..offset + 2]);
+	// println!("Offset is {}", offset);
+	let count = match contents.read_at(offset, layout.count_size()) {
+		Some(ref raw) if layout.bigtiff => read_u64(layout.le, raw) as usize,
+		Some(raw) => read_u16(layout.le, &raw) as usize,
+		None => return Err(ExifError::ExifIfdTruncated("Truncated at dir entry count".to_string())),
+	};
 	// println!("IFD entry count is {}", count);
-	let ifd_length = (count as usize) * 12;
-	offset += 2;
+	let ifd_length = count.saturating_mul(layout.entry_size());
+	offset += layout.count_size();
 
-	if contents.len() < (offset + ifd_length) {
-		return Err(ExifError::ExifIfdTruncated("Truncated at dir listing".to_string()));
-	}
+	// The next IFD offset follows the directory listing. It may be missing
+	// in truncated files, in which case the IFD is taken as the last one.
+	let (listing, has_next) = match contents.read_at(offset, ifd_length.saturating_add(layout.offset_size())) {
+		Some(listing) => (listing, true),
+		None => match contents.read_at(offset, ifd_length) {
+			Some(listing) => (listing, false),
+			None => return Err(ExifError::ExifIfdTruncated("Truncated at dir listing".to_string())),
+		},
+	};
+
+	let (mut ifd, next_ifd) = parse_ifd(kind, !has_next, layout, count, &listing);
 
-	let (mut ifd, _) = parse_ifd(true, le, count, &contents[offset..offset + ifd_length]);
+	if ifd.len() > budget.entries {
+		ifd.truncate(budget.entries);
+		warnings.push(ExifWarning {
+			tag: 0,
+			ifd_kind: kind,
+			offset: ioffset,
+			kind: ExifWarningKind::IfdTruncated("Too many IFD entries".to_string()),
+		});
+	}
+	budget.entries -= ifd.len();
 
-	for entry in &mut ifd {
-		if ! entry.copy_data(contents) {
+	for (i, entry) in ifd.iter_mut().enumerate() {
+		let entry_offset = offset + i * layout.entry_size();
+		if ! entry.copy_data_from(contents) {
 			// data is probably beyond EOF
+			warnings.push(ExifWarning {
+				tag: entry.tag,
+				ifd_kind: kind,
+				offset: entry_offset,
+				kind: ExifWarningKind::DataBeyondEof,
+			});
 			continue;
 		}
-		let exif_entry = parse_exif_entry(entry);
+		let exif_entry = parse_exif_entry(entry, entry_offset, options, warnings);
 		exif_entries.push(exif_entry);
 	}
 
-	return Ok(());
+	return Ok(next_ifd);
 }
 
-/// Parses IFD0 and looks for SubIFD or GPS IFD within IFD0
-pub fn parse_ifds(le: bool, ifd0_offset: usize, contents: &[u8]) -> ExifEntryResult
+/// Offset of the IFD pointed by an entry. The value is preferred to the raw IFD
+/// data, since a LONG in the 8-byte data area of a BigTIFF entry is left-justified.
+fn ifd_pointer(entry: &ExifEntry) -> usize
+{
+	match entry.value.to_i64(0) {
+		Some(offset) if offset >= 0 => offset as usize,
+		_ => entry.ifd.data_as_offset(),
+	}
+}
+
+/// Parses IFD0 and looks for SubIFD or GPS IFD within IFD0, and for the
+/// Interoperability IFD within SubIFD. Then parses IFD1
+pub fn parse_ifds<S: ByteSource>(layout: TiffLayout, ifd0_offset: usize, contents: &mut S,
+				options: &ParseOptions, warnings: &mut Vec<ExifWarning>) -> ExifEntryResult
 {
-	let mut offset = ifd0_offset;
 	let mut exif_entries: Vec<ExifEntry> = Vec::new();
+	let mut budget = Budget::new();
 
 	// fills exif_entries with data from IFD0
 
-	match parse_exif_ifd(le, contents, offset, &mut exif_entries) {
-		Ok(_) => true,
-		Err(e) => return Err(e),
-	};
+	let next_ifd = parse_exif_ifd(IfdKind::Ifd0, layout, contents, ifd0_offset,
+					&mut exif_entries, options, warnings, &mut budget)?;
 
 	// at this point we knot that IFD0 is good
-	// looks for SubIFD (EXIF)
-
-	let count = read_u16(le, &contents[offset..offset + 2]);
-	let ifd_length = (count as usize) * 12 + 4;
-	offset += 2;
+	// looks for SubIFD (EXIF) and GPS IFD among the IFD0 entries
 
-	let (ifd, _) = parse_ifd(false, le, count, &contents[offset..offset + ifd_length]);
+	let mut sub_ifds: Vec<(IfdKind, usize)> = Vec::new();
 
-	for entry in &ifd {
-		if entry.tag != (((ExifTag::ExifOffset as u32) & 0xffff) as u16) &&
-				entry.tag != (((ExifTag::GPSOffset as u32) & 0xffff) as u16) {
+	for entry in &exif_entries {
+		let kind = if entry.ifd.tag == (((ExifTag::ExifOffset as u32) & 0xffff) as u16) {
+			IfdKind::Exif
+		} else if entry.ifd.tag == (((ExifTag::GPSOffset as u32) & 0xffff) as u16) {
+			IfdKind::Gps
+		} else {
 			continue;
+		};
+		sub_ifds.push((kind, ifd_pointer(entry)));
+	}
+
+	for (kind, exif_offset) in sub_ifds {
+		if contents.size() < exif_offset {
+			return Err(ExifError::ExifIfdTruncated("Exif SubIFD goes past EOF".to_string()));
 		}
 
-		let exif_offset = entry.data_as_offset();
+		parse_exif_ifd(kind, layout, contents, exif_offset, &mut exif_entries, options, warnings, &mut budget)?;
+	}
 
-		if contents.len() < exif_offset {
-			return Err(ExifError::ExifIfdTruncated("Exif SubIFD goes past EOF".to_string()));
+	// Interoperability IFD is pointed by the Exif SubIFD. It is optional, and
+	// not important enough to make the whole parsing fail.
+	let mut interop_offset = None;
+	for entry in &exif_entries {
+		if entry.ifd_kind == IfdKind::Exif && entry.tag == ExifTag::InteropOffset {
+			interop_offset = Some(ifd_pointer(entry));
 		}
+	}
+	if let Some(interop_offset) = interop_offset {
+		let r = parse_exif_ifd(IfdKind::Interop, layout, contents, interop_offset,
+					&mut exif_entries, options, warnings, &mut budget);
+		warn_broken_ifd(r, IfdKind::Interop, interop_offset, warnings);
+	}
+
+	// IFD1 normally describes the thumbnail. It is optional, so a broken IFD1
+	// does not prevent the EXIF data of the main image from being returned.
+	if next_ifd != 0 && next_ifd != ifd0_offset && next_ifd < contents.size() {
+		let r = parse_exif_ifd(IfdKind::Ifd1, layout, contents, next_ifd,
+					&mut exif_entries, options, warnings, &mut budget);
+		warn_broken_ifd(r, IfdKind::Ifd1, next_ifd, warnings);
+	}
 
-		match parse_exif_ifd(le, contents, exif_offset, &mut exif_entries) {
-			Ok(_) => true,
-			Err(e) => return Err(e),
+	postprocess_entries(&mut exif_entries);
+
+	return Ok(exif_entries);
+}
+
+/// Turns the failure to parse an optional IFD into a warning
+fn warn_broken_ifd(r: NextIfdResult, kind: IfdKind, offset: usize, warnings: &mut Vec<ExifWarning>)
+{
+	if let Err(e) = r {
+		warnings.push(ExifWarning {
+			tag: 0,
+			ifd_kind: kind,
+			offset,
+			kind: ExifWarningKind::IfdTruncated(e.to_string()),
+		});
+	}
+}
+
+/// Maximum nesting level of SubIFDs. Real-world files use one or two levels.
+const MAX_SUBIFD_DEPTH: usize = 4;
+
+/// Parses an IFD as a standalone directory, and its SubIFDs recursively.
+/// Returns the directory and the offset of the next IFD in chain.
+#[allow(clippy::too_many_arguments)]
+fn parse_directory<S: ByteSource>(kind: IfdKind, index: usize, layout: TiffLayout, offset: usize, contents: &mut S,
+				options: &ParseOptions, visited: &mut HashSet<usize>, depth: usize,
+				budget: &mut Budget) -> Result<(IfdDirectory, usize), ExifError>
+{
+	let mut entries: Vec<ExifEntry> = Vec::new();
+	let mut warnings: Vec<ExifWarning> = Vec::new();
+	let next_ifd = parse_exif_ifd(kind, layout, contents, offset, &mut entries, options, &mut warnings, budget)?;
+
+	postprocess_entries(&mut entries);
+
+	let mut children: Vec<IfdDirectory> = Vec::new();
+
+	if depth < MAX_SUBIFD_DEPTH {
+		let mut sub_offsets: Vec<usize> = Vec::new();
+		for entry in &entries {
+			if entry.ifd.tag != (((ExifTag::SubIFDs as u32) & 0xffff) as u16) {
+				continue;
+			}
+			let mut i = 0;
+			while let Some(sub_offset) = entry.value.to_i64(i) {
+				sub_offsets.push(sub_offset as usize);
+				i += 1;
+			}
+		}
+
+		for sub_offset in sub_offsets {
+			if sub_offset == 0 || !visited.insert(sub_offset) {
+				// null pointer or loop
+				continue;
+			}
+			let index = children.len();
+			let sub_kind = IfdKind::SubIfd(index as u32);
+			// a broken SubIFD does not invalidate its parent
+			match parse_directory(sub_kind, index, layout, sub_offset,
+						contents, options, visited, depth + 1, budget) {
+				Ok((child, _)) => children.push(child),
+				Err(e) => warn_broken_ifd(Err(e), sub_kind, sub_offset, &mut warnings),
+			}
+		}
+	}
+
+	let directory = IfdDirectory {
+		index,
+		offset,
+		entries,
+		warnings,
+		children,
+	};
+
+	return Ok((directory, next_ifd));
+}
+
+/// Walks the whole IFD chain (IFD0, IFD1, IFD2...) and parses each IFD on its own,
+/// e.g. every page of a multi-page TIFF. Private IFDs (Exif, GPS) are not followed,
+/// but SubIFDs are, and become children of the IFD that points to them.
+///
+/// The walk stops when an IFD points back to an IFD already visited. Only a broken
+/// IFD0 is an error; a broken IFD further down the chain ends the walk, with a
+/// warning in the IFD before it.
+pub fn parse_ifd_chain<S: ByteSource>(layout: TiffLayout, ifd0_offset: usize, contents: &mut S,
+				options: &ParseOptions) -> IfdDirectoryResult
+{
+	let mut directories: Vec<IfdDirectory> = Vec::new();
+	let mut visited: HashSet<usize> = HashSet::new();
+	let mut budget = Budget::new();
+	let mut offset = ifd0_offset;
+
+	while offset != 0 && visited.insert(offset) {
+		let index = directories.len();
+		let kind = match index {
+			0 => IfdKind::Ifd0,
+			1 => IfdKind::Ifd1,
+			n => IfdKind::Page(n as u32),
+		};
+
+		let (directory, next_ifd) = match parse_directory(kind, index, layout, offset,
+									contents, options, &mut visited, 0, &mut budget) {
+			Ok(r) => r,
+			Err(e) => {
+				if index == 0 {
+					return Err(e);
+				}
+				// reported by the last good IFD, that points to the broken one
+				if let Some(last) = directories.last_mut() {
+					warn_broken_ifd(Err(e), kind, offset, &mut last.warnings);
+				}
+				break;
+			}
 		};
+
+		directories.push(directory);
+		offset = next_ifd;
 	}
 
+	return Ok(directories);
+}
+
+/// Runs postprocessing of every entry, that may depend on other entries
+fn postprocess_entries(exif_entries: &mut [ExifEntry])
+{
 	// I didn't want to make the copy, but how to pass a vector that is
 	// being iterated onto?
-	let exif_entries_copy = exif_entries.clone();
+	let exif_entries_copy = exif_entries.to_vec();
 
-	for entry in &mut exif_entries {
+	for entry in exif_entries.iter_mut() {
 		exif_postprocessing(entry, &exif_entries_copy);
 	}
+}
 
-	return Ok(exif_entries);
+/// Value of an IFD1 entry, regardless of the entry having been recognized as an Exif tag
+fn ifd1_value(entries: &[ExifEntry], tag: u16) -> Option<&TagValue>
+{
+	for entry in entries {
+		if entry.ifd_kind == IfdKind::Ifd1 && entry.ifd.tag == tag {
+			return Some(&entry.value);
+		}
+	}
+	None
 }
 
-/// Parse a TIFF image, or embedded TIFF in JPEG, in order to get IFDs and then the EXIF data
-pub fn parse_tiff(contents: &[u8]) -> ExifEntryResult
+/// Largest thumbnail accepted. Real-world thumbnails take a few kilobytes.
+const MAX_THUMBNAIL_SIZE: usize = 1024 * 1024;
+
+/// Gets the bytes of the thumbnail described by IFD1. It is either a JPEG stream
+/// (JPEGInterchangeFormat and JPEGInterchangeFormatLength tags) or a list of
+/// strips (StripOffsets and StripByteCounts tags). Offsets are relative to the
+/// start of the TIFF block.
+///
+/// Strips are only taken as a thumbnail if NewSubfileType says that IFD1 is a
+/// reduced-resolution image, since in a multi-page TIFF, IFD1 is the next page.
+/// Overlapping strips, and thumbnails bigger than `MAX_THUMBNAIL_SIZE`, are
+/// rejected.
+fn find_thumbnail<S: ByteSource>(entries: &[ExifEntry], contents: &mut S) -> Option<Vec<u8>>
 {
-	let mut le = false;
+	let jpeg_offset = ifd1_value(entries, ((ExifTag::JPEGInterchangeFormat as u32) & 0xffff) as u16);
+	let jpeg_length = ifd1_value(entries, ((ExifTag::JPEGInterchangeFormatLength as u32) & 0xffff) as u16);
 
-	if contents.len() < 8 {
-		return Err(ExifError::TiffTruncated);
-	} else if contents[0] == b'I' &&
-			contents[1] == b'I' &&
-			contents[2] == 42 && contents[3] == 0 {
+	if let (Some(offset), Some(length)) = (jpeg_offset, jpeg_length) {
+		let offset = offset.to_i64(0)?;
+		let length = length.to_i64(0)?;
+		if offset < 0 || length <= 0 || length as usize > MAX_THUMBNAIL_SIZE {
+			return None;
+		}
+		return contents.read_at(offset as usize, length as usize);
+	}
+
+	let subfile_type = ifd1_value(entries, ((ExifTag::NewSubfileType as u32) & 0xffff) as u16)?;
+	if subfile_type.to_i64(0)? & 1 == 0 {
+		// not a reduced-resolution image
+		return None;
+	}
+
+	let strip_offsets = ifd1_value(entries, ((ExifTag::StripOffsets as u32) & 0xffff) as u16)?;
+	let strip_lengths = ifd1_value(entries, ((ExifTag::StripByteCounts as u32) & 0xffff) as u16)?;
+
+	let mut strips: Vec<(usize, usize)> = Vec::new();
+	let mut total: usize = 0;
+	while let (Some(offset), Some(length)) = (strip_offsets.to_i64(strips.len()), strip_lengths.to_i64(strips.len())) {
+		if offset < 0 || length < 0 {
+			return None;
+		}
+		total = total.saturating_add(length as usize);
+		if total > MAX_THUMBNAIL_SIZE {
+			return None;
+		}
+		strips.push((offset as usize, length as usize));
+	}
+
+	let mut sorted = strips.clone();
+	sorted.sort();
+	if sorted.windows(2).any(|pair| pair[0].0.saturating_add(pair[0].1) > pair[1].0) {
+		return None;
+	}
+
+	let mut thumbnail: Vec<u8> = Vec::with_capacity(total);
+	for (offset, length) in strips {
+		thumbnail.extend(contents.read_at(offset, length)?);
+	}
+
+	if thumbnail.is_empty() {
+		return None;
+	}
+
+	return Some(thumbnail);
+}
+
+fn bad_preamble(contents: &[u8]) -> ExifError
+{
+	let err = format!("Preamble is {:x} {:x} {:x} {:x}",
+		contents[0], contents[1],
+		contents[2], contents[3]);
+	return ExifError::TiffBadPreamble(err.to_string());
+}
+
+/// Checks the TIFF header, returns layout and IFD0 offset. Besides the
+/// standard magic number 42, BigTIFF (43) and the variants of Olympus ORF
+/// and Panasonic RW2 are accepted.
+fn parse_tiff_header<S: ByteSource>(contents: &mut S) -> Result<(TiffLayout, usize), ExifError>
+{
+	let source = contents;
+	let contents = match source.read_at(0, 8) {
+		Some(header) => header,
+		None => return Err(ExifError::TiffTruncated),
+	};
+
+	let le = if contents[0..2] == b"II"[..] {
 		/* TIFF little-endian */
-		le = true;
-	} else if contents[0] == b'M' && contents[1] == b'M' &&
-			contents[2] == 0 && contents[3] == 42 {
+		true
+	} else if contents[0..2] == b"MM"[..] {
 		/* TIFF big-endian */
+		false
 	} else {
-		let err = format!("Preamble is {:x} {:x} {:x} {:x}",
-			contents[0], contents[1],
-			contents[2], contents[3]);
-		return Err(ExifError::TiffBadPreamble(err.to_string()));
+		return Err(bad_preamble(&contents));
+	};
+
+	match read_u16(le, &contents[2..4]) {
+		42 => {},
+		// BigTIFF: offset size (always 8), a zero word, then the IFD0 offset
+		43 => {
+			if read_u16(le, &contents[4..6]) != 8 || read_u16(le, &contents[6..8]) != 0 {
+				return Err(bad_preamble(&contents));
+			}
+			let offset = match source.read_at(8, 8) {
+				Some(raw) => read_u64(le, &raw) as usize,
+				None => return Err(ExifError::TiffTruncated),
+			};
+			return Ok((TiffLayout { le, bigtiff: true }, offset));
+		},
+		// Olympus ORF ("IIRO", "MMOR", "IIRS")
+		0x4f52 | 0x5352 => {},
+		// Panasonic RW2
+		0x55 => {},
+		_ => return Err(bad_preamble(&contents)),
 	}
 
 	let offset = read_u32(le, &contents[4..8]) as usize;
 
-	return parse_ifds(le, offset, &contents);
+	return Ok((TiffLayout { le, bigtiff: false }, offset));
+}
+
+/// Parse a TIFF image, or embedded TIFF in JPEG, in order to get IFDs and then the EXIF data.
+/// The `mime` member of the result is left empty, since the TIFF block may have been
+/// found inside another type of image.
+pub fn parse_tiff(contents: &[u8]) -> ExifResult
+{
+	return parse_tiff_with_options(contents, &ParseOptions::default());
+}
+
+/// Same as `parse_tiff`, with parser options
+pub fn parse_tiff_with_options(contents: &[u8], options: &ParseOptions) -> ExifResult
+{
+	let mut contents = contents;
+	return parse_tiff_source(&mut contents, options);
+}
+
+/// Same as `parse_tiff`, but reads only the IFDs and the data they point to
+/// from the source, instead of having the whole TIFF block in memory
+pub fn parse_tiff_source<S: ByteSource>(contents: &mut S, options: &ParseOptions) -> ExifResult
+{
+	let (layout, offset) = parse_tiff_header(contents)?;
+
+	let mut warnings: Vec<ExifWarning> = Vec::new();
+	let entries = parse_ifds(layout, offset, contents, options, &mut warnings)?;
+	let thumbnail = find_thumbnail(&entries, contents);
+	let xmp = xmp_from_entries(&entries);
+	let iptc = iptc_from_entries(&entries);
+
+	return Ok(ExifData {
+		mime: "".to_string(),
+		entries,
+		thumbnail,
+		warnings,
+		xmp,
+		iptc,
+	});
+}
+
+/// Parse only the first IFD of a TIFF block, taking it as an IFD of the given kind.
+/// Used by formats that store each IFD as a standalone TIFF block, e.g. CR3.
+/// The IFD chain and the pointers to private IFDs are not followed.
+pub fn parse_tiff_block<S: ByteSource>(contents: &mut S, kind: IfdKind, options: &ParseOptions) -> ExifResult
+{
+	let (layout, offset) = parse_tiff_header(contents)?;
+
+	let mut entries: Vec<ExifEntry> = Vec::new();
+	let mut warnings: Vec<ExifWarning> = Vec::new();
+	parse_exif_ifd(kind, layout, contents, offset, &mut entries, options, &mut warnings, &mut Budget::new())?;
+	postprocess_entries(&mut entries);
+
+	return Ok(ExifData {
+		mime: "".to_string(),
+		entries,
+		thumbnail: None,
+		warnings,
+		xmp: Vec::new(),
+		iptc: Vec::new(),
+	});
+}
+
+/// Parse a TIFF image, or embedded TIFF in JPEG, and returns every IFD of the IFD chain
+pub fn parse_tiff_pages(contents: &[u8], options: &ParseOptions) -> IfdDirectoryResult
+{
+	let mut contents = contents;
+	return parse_tiff_pages_source(&mut contents, options);
+}
+
+/// Same as `parse_tiff_pages`, but reads only the IFDs from the source
+pub fn parse_tiff_pages_source<S: ByteSource>(contents: &mut S, options: &ParseOptions) -> IfdDirectoryResult
+{
+	let (layout, offset) = parse_tiff_header(contents)?;
+	return parse_ifd_chain(layout, offset, contents, options);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testutil::*;
+
+	#[test]
+	fn jpeg_thumbnail() {
+		for &le in &[true, false] {
+			let d = parse_tiff(&sample_exif(le)).unwrap();
+			assert_eq!(d.thumbnail(), Some(&b"\xff\xd8thumbnail\xff\xd9"[..]));
+			let d = ::parse_buffer(&jpeg_with_exif(&sample_exif(le))).unwrap();
+			assert_eq!(d.thumbnail(), Some(&b"\xff\xd8thumbnail\xff\xd9"[..]));
+		}
+	}
+
+	/// TIFF whose IFD1 has two strips and the given extra entries
+	fn strip_ifd1(extra: &[(u16, u64)], strips: &[(&[u8], bool)]) -> Vec<u8>
+	{
+		let mut tiff = Tiff::new(true);
+		let mut offsets: Vec<u64> = Vec::new();
+		let mut lengths: Vec<u64> = Vec::new();
+		for &(strip, overlap) in strips {
+			let offset = tiff.blob(strip);
+			// an overlapping strip starts within the previous one
+			offsets.push(if overlap {offset - 1} else {offset});
+			lengths.push(strip.len() as u64);
+		}
+		let mut entries: Vec<Entry> = extra.iter().map(|&(tag, value)| entry(tag, 4, &[value])).collect();
+		entries.push(entry(0x0111, 4, &offsets));
+		entries.push(entry(0x0117, 4, &lengths));
+		let ifd1 = tiff.ifd(&entries, 0);
+		let ifd0 = tiff.ifd(&[entry(0x0100, 3, &[64])], ifd1);
+		return tiff.finish(ifd0);
+	}
+
+	#[test]
+	fn strip_thumbnail() {
+		// NewSubfileType 1: reduced-resolution image
+		let tiff = strip_ifd1(&[(0x00fe, 1)], &[(b"abc", false), (b"def", false)]);
+		assert_eq!(parse_tiff(&tiff).unwrap().thumbnail(), Some(&b"abcdef"[..]));
+
+		let tiff = strip_ifd1(&[(0x00fe, 1)], &[(b"abc", false), (b"def", true)]);
+		assert_eq!(parse_tiff(&tiff).unwrap().thumbnail(), None);
+	}
+
+	#[test]
+	fn second_page_is_not_a_thumbnail() {
+		let tiff = strip_ifd1(&[], &[(b"abc", false), (b"def", false)]);
+		assert_eq!(parse_tiff(&tiff).unwrap().thumbnail(), None);
+
+		let tiff = strip_ifd1(&[(0x00fe, 2)], &[(b"abc", false), (b"def", false)]);
+		assert_eq!(parse_tiff(&tiff).unwrap().thumbnail(), None);
+	}
+
+	#[test]
+	fn thumbnail_size_limit() {
+		// many strips that cover the same data, or a huge JPEG
+		let mut tiff = Tiff::new(true);
+		let data = tiff.blob(&[0u8; 4096]);
+		let ifd1 = tiff.ifd(&[
+			entry(0x00fe, 4, &[1]),
+			entry(0x0111, 4, &[data; 1000]),
+			entry(0x0117, 4, &[4096; 1000]),
+		], 0);
+		let ifd0 = tiff.ifd(&[entry(0x0100, 3, &[64])], ifd1);
+		assert_eq!(parse_tiff(&tiff.finish(ifd0)).unwrap().thumbnail(), None);
+
+		let mut tiff = Tiff::new(true);
+		tiff.blob(&vec![0u8; MAX_THUMBNAIL_SIZE + 1]);
+		let ifd1 = tiff.ifd(&[entry(0x0201, 4, &[8]), entry(0x0202, 4, &[MAX_THUMBNAIL_SIZE as u64 + 1])], 0);
+		let ifd0 = tiff.ifd(&[entry(0x0100, 3, &[64])], ifd1);
+		assert_eq!(parse_tiff(&tiff.finish(ifd0)).unwrap().thumbnail(), None);
+	}
+
+	fn find(d: &ExifData, kind: IfdKind, tag: ExifTag) -> Option<&ExifEntry>
+	{
+		return d.entries.iter().find(|e| e.ifd_kind == kind && e.tag == tag);
+	}
+
+	#[test]
+	fn interop_ifd() {
+		for &le in &[true, false] {
+			let d = parse_tiff(&sample_exif(le)).unwrap();
+			let index = find(&d, IfdKind::Interop, ExifTag::InteroperabilityIndex).unwrap();
+			assert_eq!(index.value_more_readable, "R98 - DCF basic file (sRGB)");
+			let version = find(&d, IfdKind::Interop, ExifTag::InteroperabilityVersion).unwrap();
+			assert_eq!(version.value_more_readable, "0100");
+			// same tag code in the GPS IFD
+			let lat = find(&d, IfdKind::Gps, ExifTag::GPSLatitudeRef).unwrap();
+			assert_eq!(lat.ifd.tag, index.ifd.tag);
+			assert_eq!(lat.value_more_readable, "N");
+		}
+	}
+
+	#[test]
+	fn broken_interop_ifd() {
+		let mut tiff = Tiff::new(true);
+		let exif = tiff.ifd(&[entry(0x8827, 3, &[200]), entry(0xa005, 4, &[0xfff0])], 0);
+		let ifd0 = tiff.ifd(&[entry(0x8769, 4, &[exif])], 0);
+		let d = parse_tiff(&tiff.finish(ifd0)).unwrap();
+		assert!(find(&d, IfdKind::Exif, ExifTag::ISOSpeedRatings).is_some());
+		assert!(d.entries.iter().all(|e| e.ifd_kind != IfdKind::Interop));
+	}
+
+	#[test]
+	fn short_or_long() {
+		let mut tiff = Tiff::new(true);
+		let ifd1 = tiff.ifd(&[entry(0x0201, 3, &[100]), entry(0x0202, 3, &[10])], 0);
+		let ifd0 = tiff.ifd(&[entry(0x0100, 3, &[640]), entry(0x0101, 4, &[480])], ifd1);
+		let d = parse_tiff(&tiff.finish(ifd0)).unwrap();
+
+		let width = find(&d, IfdKind::Ifd0, ExifTag::ImageWidth).unwrap();
+		assert_eq!(width.value.to_i64(0), Some(640));
+		assert!(matches!(width.value, TagValue::U32(_)));
+		assert!(find(&d, IfdKind::Ifd0, ExifTag::ImageLength).is_some());
+		// only LONG is allowed for the thumbnail offset and length
+		assert!(d.entries.iter().all(|e| e.ifd_kind != IfdKind::Ifd1 || e.tag == ExifTag::UnknownToMe));
+	}
+
+	#[test]
+	fn page_chain() {
+		let mut tiff = Tiff::new(false);
+		let page2 = tiff.ifd(&[entry(0x0100, 4, &[30]), ascii(0x010e, "third")], 8);
+		let page1 = tiff.ifd(&[entry(0x0100, 4, &[20]), ascii(0x010e, "second")], page2);
+		let page0 = tiff.ifd(&[entry(0x0100, 4, &[10]), ascii(0x010e, "first")], page1);
+		let mut tiff = tiff.finish(page0);
+		// the last page points back to the first one
+		let at = page2 as usize + 2 + 2 * 12;
+		tiff[at..at + 4].copy_from_slice(&(page0 as u32).to_be_bytes());
+
+		let pages = ::parse_buffer_pages(&tiff).unwrap();
+		assert_eq!(pages.len(), 3);
+		let kinds = [IfdKind::Ifd0, IfdKind::Ifd1, IfdKind::Page(2)];
+		for (i, page) in pages.iter().enumerate() {
+			assert_eq!(page.index, i);
+			assert!(page.entries.iter().all(|e| e.ifd_kind == kinds[i]));
+			let width = page.entries.iter().find(|e| e.tag == ExifTag::ImageWidth).unwrap();
+			assert_eq!(width.value.to_i64(0), Some(10 * (i as i64 + 1)));
+		}
+		assert_eq!(pages[2].offset, page2 as usize);
+		assert_eq!(pages[2].entries[1].value_more_readable, "third");
+	}
+
+	#[test]
+	fn subifds() {
+		let mut tiff = Tiff::new(true);
+		let raw = tiff.ifd(&[entry(0x00fe, 4, &[0]), entry(0x0100, 4, &[6000])], 0);
+		// SubIFDs of format IFD
+		let preview = tiff.ifd(&[entry(0x00fe, 4, &[1]), entry(0x0100, 4, &[1024])], 0);
+		// IFD format is only allowed for pointers
+		let nested = tiff.ifd(&[entry(0x0100, 4, &[160]), entry(0x0101, 13, &[120])], 0);
+		let preview_with_child = tiff.ifd(&[entry(0x0100, 4, &[512]), entry(0x014a, 13, &[nested])], 0);
+		let ifd0 = tiff.ifd(&[
+			entry(0x0100, 4, &[256]),
+			entry(0x014a, 4, &[raw, preview, preview_with_child, 0xfff0]),
+		], 0);
+		let pages = ::parse_buffer_pages(&tiff.finish(ifd0)).unwrap();
+		assert_eq!(pages.len(), 1);
+
+		let children = &pages[0].children;
+		assert_eq!(children.len(), 3);
+		for (i, (child, &width)) in children.iter().zip(&[6000, 1024, 512]).enumerate() {
+			assert_eq!(child.index, i);
+			let e = child.entries.iter().find(|e| e.tag == ExifTag::ImageWidth).unwrap();
+			assert_eq!(e.ifd_kind, IfdKind::SubIfd(i as u32));
+			assert_eq!(e.value.to_i64(0), Some(width));
+		}
+		assert_eq!(children[0].offset, raw as usize);
+		assert_eq!(children[2].children.len(), 1);
+		assert_eq!(children[2].children[0].entries[0].value.to_i64(0), Some(160));
+		assert_eq!(children[2].children[0].entries[1].tag, ExifTag::UnknownToMe);
+	}
+
+	#[test]
+	fn subifd_loop_and_depth() {
+		// each SubIFD points to the next one, and the last one back to IFD0
+		let mut tiff = Tiff::new(true);
+		let mut offsets: Vec<u64> = Vec::new();
+		for _ in 0..8 {
+			let offset = tiff.ifd(&[entry(0x014a, 4, &[0])], 0);
+			offsets.push(offset);
+		}
+		let mut data = tiff.finish(offsets[0]);
+		for (i, &offset) in offsets.iter().enumerate() {
+			let next = if i + 1 < offsets.len() {offsets[i + 1]} else {offsets[0]};
+			let at = offset as usize + 2 + 8;
+			data[at..at + 4].copy_from_slice(&(next as u32).to_le_bytes());
+		}
+		let pages = ::parse_buffer_pages(&data).unwrap();
+		let mut depth = 0;
+		let mut dir = &pages[0];
+		while let Some(child) = dir.children.first() {
+			assert_eq!(dir.children.len(), 1);
+			dir = child;
+			depth += 1;
+		}
+		assert_eq!(depth, MAX_SUBIFD_DEPTH);
+	}
+
+	/// Parses the image in every possible way, which must not panic
+	fn parse_all(data: &[u8])
+	{
+		let _ = parse_tiff(data);
+		let _ = ::parse_buffer(data);
+		let _ = ::parse_buffer_pages(data);
+	}
+
+	#[test]
+	fn truncated() {
+		let tiff = sample_exif(true);
+		let jpeg = jpeg_with_exif(&sample_exif(false));
+		for n in 0..tiff.len() {
+			parse_all(&tiff[..n]);
+		}
+		for n in 0..jpeg.len() {
+			parse_all(&jpeg[..n]);
+		}
+	}
+
+	#[test]
+	fn loops() {
+		// IFD0 is its own next IFD, Exif IFD and SubIFD, and the Interop IFD
+		// is the Exif IFD
+		let mut tiff = Tiff::new(true);
+		let ifd0 = tiff.ifd(&[
+			entry(0x014a, 4, &[8, 8]),
+			entry(0x8769, 4, &[8]),
+			entry(0xa005, 4, &[8]),
+		], 8);
+		assert_eq!(ifd0, 8);
+		let data = tiff.finish(ifd0);
+		parse_all(&data);
+		let pages = ::parse_buffer_pages(&data).unwrap();
+		assert_eq!(pages.len(), 1);
+		assert!(pages[0].children.is_empty());
+	}
+
+	#[test]
+	fn overlaps() {
+		// the data of the entries is the IFD itself or the header, and the
+		// Exif IFD is IFD0
+		let mut tiff = Tiff::new(false);
+		let ifd0 = tiff.ifd(&[
+			entry(0x0111, 4, &[0, 1, 2]),
+			entry(0x0117, 4, &[4, 4, 4]),
+			entry(0x8769, 4, &[0]),
+		], 0);
+		let mut data = tiff.finish(ifd0);
+		for i in 0..3 {
+			let at = ifd0 as usize + 2 + i * 12 + 8;
+			let target = [ifd0 as u32, 0, ifd0 as u32][i];
+			data[at..at + 4].copy_from_slice(&target.to_be_bytes());
+		}
+		parse_all(&data);
+		let d = parse_tiff(&data).unwrap();
+		assert!(d.entries.iter().any(|e| e.tag == ExifTag::StripOffsets));
+		assert!(d.entries.iter().any(|e| e.ifd_kind == IfdKind::Exif));
+
+		// past EOF
+		let at = ifd0 as usize + 2 + 2 * 12 + 8;
+		data[at..at + 4].copy_from_slice(&0xffff_fff0u32.to_be_bytes());
+		parse_all(&data);
+	}
+
+	#[test]
+	fn ifd_limit() {
+		let mut tiff = Tiff::new(true);
+		let mut next = 0;
+		for _ in 0..MAX_IFDS + 10 {
+			next = tiff.ifd(&[entry(0x0100, 4, &[1])], next);
+		}
+		let pages = ::parse_buffer_pages(&tiff.finish(next)).unwrap();
+		assert_eq!(pages.len(), MAX_IFDS);
+	}
+
+	#[test]
+	fn entry_limit() {
+		let entries: Vec<Entry> = (0..40000).map(|_| entry(0xffff, 1, &[0])).collect();
+		let mut tiff = Tiff::new(true);
+		let exif = tiff.ifd(&entries, 0);
+		let mut ifd0_entries: Vec<Entry> = (0..40000).map(|_| entry(0xffff, 1, &[0])).collect();
+		ifd0_entries.push(entry(0x8769, 4, &[exif]));
+		let ifd0 = tiff.ifd(&ifd0_entries, 0);
+		let d = parse_tiff(&tiff.finish(ifd0)).unwrap();
+		assert_eq!(d.entries.len(), MAX_ENTRIES);
+	}
+
+	#[test]
+	fn warnings() {
+		let mut tiff = Tiff::new(true);
+		let exif = tiff.ifd(&[
+			// RATIONAL expected
+			entry(0x829a, 3, &[250]),
+			// up to three SHORTs expected
+			entry(0x8827, 3, &[100, 200, 400, 800]),
+			// data past EOF
+			undefined(0x927c, b"maker note"),
+			entry(0xa005, 4, &[0xfff0]),
+		], 0);
+		let ifd0 = tiff.ifd(&[ascii(0x010f, "Canon"), entry(0x8769, 4, &[exif])], 0);
+		let mut data = tiff.finish(ifd0);
+		let at = exif as usize + 2 + 2 * 12 + 8;
+		data[at..at + 4].copy_from_slice(&0xfff8u32.to_le_bytes());
+		// IFD1 cut short
+		let ifd1 = data.len() as u64 - 1;
+		let at = ifd0 as usize + 2 + 2 * 12;
+		data[at..at + 4].copy_from_slice(&(ifd1 as u32).to_le_bytes());
+		let d = parse_tiff(&data).unwrap();
+
+		let kinds: Vec<(u16, IfdKind, ExifWarningKind)> = d.warnings.iter()
+			.map(|w| (w.tag, w.ifd_kind, w.kind.clone())).collect();
+		assert_eq!(&kinds[..3], &[
+			(0x829a, IfdKind::Exif, ExifWarningKind::WrongFormat(IfdFormat::URational, IfdFormat::U16)),
+			(0x8827, IfdKind::Exif, ExifWarningKind::CountOutOfRange(1, 3, 4)),
+			(0x927c, IfdKind::Exif, ExifWarningKind::DataBeyondEof),
+		][..]);
+		assert_eq!(d.warnings[0].offset, exif as usize + 2);
+		assert_eq!(d.warnings[2].offset, exif as usize + 2 + 2 * 12);
+		// Interop IFD and IFD1 past EOF
+		assert_eq!(d.warnings.len(), 5);
+		assert_eq!((d.warnings[3].ifd_kind, d.warnings[3].offset), (IfdKind::Interop, 0xfff0));
+		assert_eq!((d.warnings[4].ifd_kind, d.warnings[4].offset), (IfdKind::Ifd1, ifd1 as usize));
+		for w in &d.warnings[3..] {
+			assert!(matches!(w.kind, ExifWarningKind::IfdTruncated(_)));
+		}
+
+		// the entries with the wrong format or count are kept as unknown
+		assert_eq!(d.entries.iter().filter(|e| e.ifd_kind == IfdKind::Exif && e.tag == ExifTag::UnknownToMe).count(), 2);
+		assert!(d.entries.iter().all(|e| e.ifd.tag != 0x927c));
+	}
+
+	#[test]
+	fn limit_warnings() {
+		let mut tiff = Tiff::new(true);
+		let mut next = 0;
+		for _ in 0..MAX_IFDS + 10 {
+			next = tiff.ifd(&[entry(0x0100, 4, &[1])], next);
+		}
+		let pages = ::parse_buffer_pages(&tiff.finish(next)).unwrap();
+		let last = pages.last().unwrap();
+		assert_eq!(last.warnings.len(), 1);
+		assert_eq!(last.warnings[0].ifd_kind, IfdKind::Page(MAX_IFDS as u32));
+
+		let entries: Vec<Entry> = (0..40000).map(|_| entry(0xffff, 1, &[0])).collect();
+		let mut tiff = Tiff::new(true);
+		let ifd1 = tiff.ifd(&entries, 0);
+		let ifd0 = tiff.ifd(&entries, ifd1);
+		let d = parse_tiff(&tiff.finish(ifd0)).unwrap();
+		assert_eq!(d.warnings.len(), 1);
+		assert_eq!((d.warnings[0].ifd_kind, d.warnings[0].offset), (IfdKind::Ifd1, ifd1 as usize));
+	}
+
+	#[test]
+	fn lenient() {
+		let mut tiff = Tiff::new(false);
+		let exif = tiff.ifd(&[
+			// RATIONAL expected
+			entry(0x829a, 4, &[2]),
+			// SHORT expected
+			entry(0x8827, 4, &[400]),
+			entry(0x8830, 4, &[70000]),
+			// ASCII expected
+			entry(0x9003, 3, &[10]),
+		], 0);
+		let ifd0 = tiff.ifd(&[
+			entry(0x0100, 3, &[640]),
+			entry(0x0112, 4, &[6]),
+			entry(0x8769, 13, &[exif]),
+		], 0);
+		let data = tiff.finish(ifd0);
+
+		let strict = parse_tiff(&data).unwrap();
+		let lenient = parse_tiff_with_options(&data, &ParseOptions { lenient: true, ..ParseOptions::default() }).unwrap();
+
+		let coerced: Vec<u16> = lenient.warnings.iter().filter_map(|w| match w.kind {
+			ExifWarningKind::FormatCoerced(_, _) => Some(w.tag),
+			_ => None,
+		}).collect();
+		assert_eq!(coerced, vec![0x0112, 0x829a, 0x8827]);
+		let wrong: Vec<u16> = lenient.warnings.iter().filter_map(|w| match w.kind {
+			ExifWarningKind::WrongFormat(_, _) => Some(w.tag),
+			_ => None,
+		}).collect();
+		// out of range, and string expected
+		assert_eq!(wrong, vec![0x8830, 0x9003]);
+		assert_eq!(strict.warnings.len(), 5);
+		assert!(strict.warnings.iter().all(|w| matches!(w.kind, ExifWarningKind::WrongFormat(_, _))));
+
+		let orientation = find(&lenient, IfdKind::Ifd0, ExifTag::Orientation).unwrap();
+		assert!(matches!(orientation.value, TagValue::U16(ref v) if v[..] == [6]));
+		assert_eq!(orientation.value_more_readable, "Rotated to left");
+		let exposure = find(&lenient, IfdKind::Exif, ExifTag::ExposureTime).unwrap();
+		assert_eq!(exposure.value.to_f64(0), Some(2.0));
+		assert!(find(&strict, IfdKind::Ifd0, ExifTag::Orientation).is_none());
+		assert!(find(&strict, IfdKind::Exif, ExifTag::ExposureTime).is_none());
+
+		// standard alternatives, accepted in any mode
+		for d in &[&strict, &lenient] {
+			assert!(find(d, IfdKind::Ifd0, ExifTag::ImageWidth).is_some());
+			assert!(find(d, IfdKind::Ifd0, ExifTag::ExifOffset).is_some());
+		}
+	}
+
+	#[test]
+	fn bigtiff() {
+		for &le in &[true, false] {
+			let mut tiff = Tiff::new_bigtiff(le);
+			let strip = tiff.blob(b"strip data");
+			let thumbnail = tiff.blob(b"\xff\xd8thumbnail\xff\xd9");
+			let ifd1 = tiff.ifd(&[
+				entry(0x0103, 3, &[6]),
+				entry(0x0201, 16, &[thumbnail]),
+				entry(0x0202, 16, &[13]),
+			], 0);
+			let exif = tiff.ifd(&[entry(0x829a, 5, &[1, 250])], 0);
+			let ifd0 = tiff.ifd(&[
+				// LONG8 is only for offsets and sizes
+				entry(0x0100, 16, &[640]),
+				entry(0x0101, 4, &[480]),
+				ascii(0x010f, "Long enough to be out of the IFD entry"),
+				entry(0x0111, 16, &[strip, strip + 5]),
+				entry(0x0117, 16, &[5, 5]),
+				entry(0x8769, 18, &[exif]),
+			], ifd1);
+			let data = tiff.finish(ifd0);
+
+			let d = parse_tiff(&data).unwrap();
+			assert_eq!(d.thumbnail(), Some(&b"\xff\xd8thumbnail\xff\xd9"[..]));
+			assert_eq!(find(&d, IfdKind::Ifd0, ExifTag::Make).unwrap().value_more_readable,
+					"Long enough to be out of the IFD entry");
+			let offsets = find(&d, IfdKind::Ifd0, ExifTag::StripOffsets).unwrap();
+			assert!(matches!(offsets.value, TagValue::U32(ref v) if v[..] == [strip as u32, strip as u32 + 5]));
+			assert!(find(&d, IfdKind::Exif, ExifTag::ExposureTime).is_some());
+			assert_eq!(find(&d, IfdKind::Ifd0, ExifTag::ImageLength).unwrap().value.to_i64(0), Some(480));
+			assert!(find(&d, IfdKind::Ifd0, ExifTag::ImageWidth).is_none());
+			assert_eq!(d.warnings.len(), 1);
+			assert_eq!(d.warnings[0].tag, 0x0100);
+			assert!(matches!(d.warnings[0].kind, ExifWarningKind::WrongFormat(IfdFormat::U32, IfdFormat::U64)));
+
+			let lenient = parse_tiff_with_options(&data, &ParseOptions { lenient: true, ..ParseOptions::default() }).unwrap();
+			assert_eq!(find(&lenient, IfdKind::Ifd0, ExifTag::ImageWidth).unwrap().value.to_i64(0), Some(640));
+			assert_eq!(lenient.warnings.len(), 1);
+			assert!(matches!(lenient.warnings[0].kind, ExifWarningKind::FormatCoerced(IfdFormat::U32, IfdFormat::U64)));
+
+			let pages = parse_tiff_pages(&data, &ParseOptions::default()).unwrap();
+			assert_eq!(pages.len(), 2);
+			assert_eq!(pages[0].offset as u64, ifd0);
+			assert_eq!(pages[1].offset as u64, ifd1);
+		}
+	}
 }
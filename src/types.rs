@@ -1,15 +1,186 @@
 use super::rational::*;
 use std::fmt;
+use std::convert::TryFrom;
 use std::result::Result;
 use std::io;
 
 /// Top-level structure that contains all parsed metadata inside an image
 #[derive(Debug)]
 pub struct ExifData {
-	/// MIME type of the parsed image. It may be "image/jpeg", "image/tiff", or empty if unrecognized.
+	/// MIME type of the parsed image. It may be "image/jpeg", "image/tiff", "image/png",
+	/// "image/webp", "image/heic", "image/heif", "image/avif", "image/jxl",
+	/// "image/x-canon-cr3", "image/x-fuji-raf", "image/vnd.adobe.photoshop" (PSD
+	/// or PSB), "application/x-exif" for a bare
+	/// EXIF payload with "Exif\0\0" header, or empty if unrecognized. Raw formats
+	/// based on TIFF have their own MIME types: "image/x-canon-cr2", "image/x-nikon-nef",
+	/// "image/x-sony-arw", "image/x-pentax-pef", "image/x-adobe-dng",
+	/// "image/x-olympus-orf" and "image/x-panasonic-rw2".
 	pub mime: String,
-	/// Collection of EXIF entries found in the image
+	/// Collection of EXIF entries found in the image. Entries of IFD1 (the thumbnail
+	/// directory) are also here, see `ExifEntry::ifd_kind`.
 	pub entries: Vec<ExifEntry>,
+	/// Raw bytes of the thumbnail embedded in the image, as described by IFD1,
+	/// see `thumbnail()`
+	pub(crate) thumbnail: Option<Vec<u8>>,
+	/// Non-fatal problems found while parsing, e.g. tags with unexpected format
+	pub warnings: Vec<ExifWarning>,
+	/// Properties of the XMP packet found in the image, if any (JPEG APP1
+	/// segment, TIFF tag 700, PNG iTXt chunk, WebP "XMP " chunk or PSD image
+	/// resource 0x0424). The extended
+	/// XMP of JPEG images is merged in. A broken packet is ignored.
+	pub xmp: Vec<XmpProperty>,
+	/// Datasets of the IPTC-IIM record found in the image, if any (JPEG APP13
+	/// segment, TIFF tag 33723, TIFF tag 34377 or PSD image resource 0x0404),
+	/// in file order. A broken
+	/// record is ignored.
+	pub iptc: Vec<IptcDataset>,
+}
+
+/// Dataset of an IPTC-IIM record, e.g. 2:25 (Keywords). Repeatable datasets
+/// appear once per value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IptcDataset {
+	/// Record number, e.g. 1 (envelope) or 2 (application)
+	pub record: u8,
+	/// Dataset number within the record
+	pub dataset: u8,
+	/// Name of the dataset, e.g. "Keywords", or empty if unknown
+	pub name: String,
+	/// Raw data of the dataset
+	pub data: Vec<u8>,
+	/// Readable value: text decoded to UTF-8, a number, or the size of binary data
+	pub value: String,
+}
+
+/// Property of an XMP packet, e.g. dc:title or xmp:Rating
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmpProperty {
+	/// Namespace URI, e.g. "http://purl.org/dc/elements/1.1/"
+	pub namespace: String,
+	/// Namespace prefix used in the packet, e.g. "dc"
+	pub prefix: String,
+	/// Name of the property, without prefix, e.g. "title"
+	pub name: String,
+	/// Value of the property
+	pub value: XmpValue,
+}
+
+/// Value of an XMP property. Qualifiers other than xml:lang are dropped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum XmpValue {
+	/// Simple value (text, number, date, URI...) as found in the packet
+	Simple(String),
+	/// Structure, whose fields are properties on their own
+	Struct(Vec<XmpProperty>),
+	/// Unordered array (rdf:Bag), e.g. dc:subject (keywords)
+	Bag(Vec<XmpValue>),
+	/// Ordered array (rdf:Seq), e.g. dc:creator
+	Seq(Vec<XmpValue>),
+	/// Array of alternatives (rdf:Alt)
+	Alt(Vec<XmpValue>),
+	/// Language alternative, an rdf:Alt whose items have xml:lang qualifiers,
+	/// as (language, text) pairs. The default item has language "x-default".
+	LangAlt(Vec<(String, String)>),
+}
+
+/// Resource of a Photoshop Image Resource Block (IRB), see `parse_image_resources`
+#[derive(Debug)]
+pub struct ImageResource {
+	/// Resource ID, e.g. 0x0404 for IPTC-NAA
+	pub id: u16,
+	/// Name of the resource, normally empty
+	pub name: String,
+	/// Raw data of the resource
+	pub data: Vec<u8>,
+	/// Decoded value of the resource, if its format is known
+	pub value: ImageResourceValue,
+}
+
+/// Decoded value of an image resource
+#[derive(Debug)]
+pub enum ImageResourceValue {
+	/// Resource not decoded, either unknown or broken. See `ImageResource::data`.
+	Raw,
+	/// Resolution info (0x03ED). Resolutions are in pixels per inch if the
+	/// unit is 1, or pixels per centimeter if it is 2.
+	ResolutionInfo {
+		x_resolution: f64,
+		x_resolution_unit: u16,
+		/// Display unit of the width: 1 inch, 2 cm, 3 point, 4 pica, 5 column
+		width_unit: u16,
+		y_resolution: f64,
+		y_resolution_unit: u16,
+		/// Display unit of the height, as above
+		height_unit: u16,
+	},
+	/// Thumbnail (0x040C, or 0x0409 for Photoshop 4.0). The format is 1 for
+	/// a JPEG stream, or 0 for raw pixels (BGR in 0x0409, RGB in 0x040C).
+	Thumbnail {
+		format: u32,
+		width: u32,
+		height: u32,
+		data: Vec<u8>,
+	},
+	/// IPTC-NAA record (0x0404)
+	Iptc(Vec<IptcDataset>),
+	/// EXIF data (0x0422 or 0x0423), a TIFF block
+	Exif(Box<ExifData>),
+	/// XMP packet (0x0424)
+	Xmp(Vec<XmpProperty>),
+}
+
+/// Options that change the behavior of the parser. The default is to follow
+/// the standard strictly.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ParseOptions {
+	/// Accept known tags stored in a compatible integer or rational format other
+	/// than the standard one (e.g. Orientation stored as LONG instead of SHORT),
+	/// converting the value to the standard format. Each conversion is reported
+	/// as a `FormatCoerced` warning. Otherwise, such tags are kept as `UnknownToMe`.
+	///
+	/// The formats that the TIFF specification allows as alternatives are accepted
+	/// in any mode, without warning: SHORT for ImageWidth, ImageLength, StripOffsets,
+	/// RowsPerStrip and StripByteCounts, IFD for the pointers to IFDs and, in BigTIFF,
+	/// LONG8 or IFD8 for the offsets and sizes of strips, the thumbnail and the IFDs.
+	pub lenient: bool,
+	/// Verify the CRC of the PNG chunk that holds the EXIF data. A mismatch
+	/// is an error. Otherwise, the CRC is not even read.
+	pub check_crc: bool,
+}
+
+/// Non-fatal problem found while parsing an image. The affected entry is either
+/// skipped, or kept as `UnknownToMe`, and the parsing goes on.
+#[derive(Clone, Debug)]
+pub struct ExifWarning {
+	/// Tag code of the affected IFD entry. Zero if the warning is about a whole IFD.
+	pub tag: u16,
+	/// IFD of the affected entry
+	pub ifd_kind: IfdKind,
+	/// Offset of the affected IFD entry (or IFD), relative to the start of the TIFF block
+	pub offset: usize,
+	/// What went wrong
+	pub kind: ExifWarningKind,
+}
+
+/// Enumeration of the non-fatal problems that may be found while parsing
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExifWarningKind {
+	/// Tag is known but its format is not the one specified by the standard.
+	/// The entry is kept as `UnknownToMe`.
+	WrongFormat(IfdFormat, IfdFormat),
+	/// Tag is known and its format is not the one specified by the standard,
+	/// but the value was converted to it (expected, found). Only happens in
+	/// lenient mode, see `ParseOptions`.
+	FormatCoerced(IfdFormat, IfdFormat),
+	/// Tag is known but the count of items is out of the range specified by
+	/// the standard (minimum, maximum, found). The entry is kept as `UnknownToMe`.
+	CountOutOfRange(i32, i32, u32),
+	/// Data of the entry goes beyond the end of file. The entry is skipped.
+	DataBeyondEof,
+	/// An optional IFD (e.g. IFD1, Interoperability IFD, SubIFD) is broken,
+	/// and was skipped. Also used when the file has more IFDs or IFD entries
+	/// than the parser accepts, and the ones over the limit were skipped.
+	IfdTruncated(String),
 }
 
 /// Possible fatal errors that may happen when an image is parsed.
@@ -18,6 +189,16 @@ pub enum ExifError {
 	IoError(io::Error),
 	FileTypeUnknown,
 	JpegWithoutExif(String),
+	PngWithoutExif(String),
+	WebpWithoutExif(String),
+	HeifWithoutExif(String),
+	JxlWithoutExif(String),
+	Cr3WithoutExif(String),
+	RafWithoutExif(String),
+	PsdWithoutExif(String),
+	XmpMalformed(String),
+	IptcMalformed(String),
+	IrbMalformed(String),
 	TiffTruncated,
 	TiffBadPreamble(String),
 	IfdTruncated,
@@ -32,6 +213,9 @@ pub struct IfdEntry {
 	/// other namespaces are entries found e.g. within MarkerNote blobs that are
 	/// manufacturer-specific.
 	pub namespace: Namespace,
+	/// IFD the entry was found in. Tag codes are only unique within an IFD,
+	/// e.g. GPS tags reuse the codes of IFD0 tags.
+	pub ifd_kind: IfdKind,
 	/// IFD tag value, may or not be an EXIF tag
 	pub tag: u16,
 	/// IFD data format
@@ -41,8 +225,9 @@ pub struct IfdEntry {
 	/// Raw data as a vector of bytes. Length is sizeof(format) * count.
 	/// Depending on its size, it came from different parts of the image file.
 	pub data: Vec<u8>,
-	/// Raw data contained within the IFD structure. If count * sizeof(format) >= 4,
-	/// this item contains the offset where the actual data can be found
+	/// Raw data contained within the IFD structure: 4 bytes, or 8 bytes in BigTIFF.
+	/// If count * sizeof(format) does not fit, this item contains the offset where
+	/// the actual data can be found
 	pub ifd_data: Vec<u8>,
 	/// Raw data contained outside of the IFD structure and pointed by ifd_data,
 	/// if data would not fit within the IFD structure
@@ -65,34 +250,144 @@ pub enum Namespace {
 	Canon = 0x0002,
 }
 
+/// Enumeration that identifies the IFD where an entry was found. The Exif tag
+/// namespace is per IFD: the same tag code means different things in IFD0,
+/// in the GPS IFD, in the Interoperability IFD, etc.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IfdKind {
+	/// Main image IFD, the first of the TIFF IFD chain
+	Ifd0,
+	/// Second IFD of the TIFF IFD chain, which holds the thumbnail in Exif files
+	Ifd1,
+	/// Third and following IFDs of the TIFF IFD chain, e.g. additional pages
+	/// of a multi-page TIFF. Carries the position in chain (2 for IFD2, etc.)
+	Page(u32),
+	/// Exif private IFD, pointed by ExifOffset
+	Exif,
+	/// GPS private IFD, pointed by GPSOffset
+	Gps,
+	/// Interoperability private IFD, pointed by InteropOffset in Exif IFD
+	Interop,
+	/// TIFF SubIFD, pointed by the SubIFDs tag. Used by DNG and raw formats to
+	/// hold the full-resolution image and previews. Carries the position in the
+	/// list of SubIFDs of the parent IFD (0 for the first).
+	SubIfd(u32),
+	/// IFD embedded in MakerNote tag, whose contents are manufacturer-specific
+	MakerNote,
+}
+
+/// Structure that represents one IFD of the TIFF IFD chain, e.g. a page of
+/// a multi-page TIFF
+#[derive(Clone, Debug)]
+pub struct IfdDirectory {
+	/// Position of the IFD in the chain, 0 for IFD0
+	pub index: usize,
+	/// Offset of the IFD, relative to the start of the TIFF block
+	pub offset: usize,
+	/// EXIF entries found in this IFD. Entries of private IFDs pointed by
+	/// this IFD (e.g. Exif and GPS) are not included.
+	pub entries: Vec<ExifEntry>,
+	/// Non-fatal problems found while parsing this IFD
+	pub warnings: Vec<ExifWarning>,
+	/// SubIFDs pointed by the SubIFDs tag of this IFD, in the order they
+	/// are listed. For these, `index` is the position in the list.
+	pub children: Vec<IfdDirectory>,
+}
+
+/// Kind of a JPEG segment, told by its marker and, for APPn segments, by the
+/// signature at the start of the segment data
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum JpegSegmentKind {
+	/// APP0 segment with "JFIF" or "JFXX" signature
+	Jfif,
+	/// APP1 segment with "Exif" signature, that contains a TIFF block
+	Exif,
+	/// APP1 segment that contains an XMP packet
+	Xmp,
+	/// APP1 segment that contains a part of an extended XMP packet
+	XmpExtension,
+	/// APP2 segment that contains a part of an ICC profile
+	IccProfile,
+	/// APP2 segment of the Multi-Picture Format
+	Mpf,
+	/// APP13 segment with Photoshop image resources (IPTC and others)
+	Photoshop,
+	/// APP14 segment with Adobe color transform information
+	Adobe,
+	/// Other APPn segment, with its number n (0 to 15)
+	App(u8),
+	/// Comment (COM) segment
+	Comment,
+	/// Start of scan (SOS). Compressed data follows, so the scan stops here.
+	StartOfScan,
+	/// End of image (EOI)
+	EndOfImage,
+	/// Standalone marker, without length or data (RSTn, TEM)
+	Standalone,
+	/// Any other marker segment, e.g. SOFn, DHT, DQT
+	Other,
+}
+
+/// Segment of a JPEG image, as listed by `read_jpeg_segments`
+#[derive(Clone, Debug)]
+pub struct JpegSegment {
+	/// Marker code, i.e. the byte that follows 0xFF (e.g. 0xE1 for APP1)
+	pub marker: u8,
+	/// Kind of segment
+	pub kind: JpegSegmentKind,
+	/// Offset of the segment data, after the marker and the length word
+	pub offset: usize,
+	/// Size of the segment data, excluding the length word. Zero for standalone markers.
+	pub size: usize,
+}
+
 /// Enumeration that represents recognized EXIF tags found in TIFF IFDs.
 ///
-/// Items can be cast to u32 in order to get the namespace (most significant word)
-/// and tag code (least significant word). The tag code matches the Exif, or the
-/// Makernote standard, depending on the namespace that the tag belongs to.
+/// Items can be cast to u32 in order to get the tag code (least significant word).
+/// The tag code matches the Exif, or the Makernote standard, depending on the
+/// IFD and namespace that the tag belongs to. Since tag codes are only unique
+/// within an IFD, the tag code alone does not identify an `ExifTag`; it must be
+/// paired with the `IfdKind` where the tag was found.
 ///
-/// On the other hand, the namespace code is arbitrary, it only matches
-/// the `Namespace` enumeration. The namespace is 0 for standard Exif tags.
-/// The non-standard namespaces exist to accomodate future parsing of the
-/// MarkerNote tag, that contains embedded manufacturer-specific tags.
+/// The most significant word is arbitrary, it only exists to keep the enumeration
+/// values unique when two IFDs define tags with the same code. It is 0 for the
+/// standard TIFF, Exif and GPS tags, and 0x0001 for the Interoperability tags,
+/// whose codes overlap with the GPS ones.
 #[derive(Copy, Clone, Debug, PartialEq, Hash)]
 pub enum ExifTag {
 	/// Tag not recognized are partially parsed. The client may still try to interpret
 	/// the tag by reading into the IfdFormat structure.
 	UnknownToMe = 0x0000ffff,
+	NewSubfileType = 0x000000fe,
+	ImageWidth = 0x00000100,
+	ImageLength = 0x00000101,
+	BitsPerSample = 0x00000102,
+	Compression = 0x00000103,
+	PhotometricInterpretation = 0x00000106,
+	DocumentName = 0x0000010d,
 	ImageDescription = 0x0000010e,
 	Make = 0x0000010f,
 	Model = 0x00000110,
+	StripOffsets = 0x00000111,
 	Orientation = 0x00000112,
+	SamplesPerPixel = 0x00000115,
+	RowsPerStrip = 0x00000116,
+	StripByteCounts = 0x00000117,
 	XResolution = 0x0000011a,
 	YResolution = 0x0000011b,
+	PlanarConfiguration = 0x0000011c,
+	PageName = 0x0000011d,
 	ResolutionUnit = 0x00000128,
+	PageNumber = 0x00000129,
 	Software = 0x00000131,
 	DateTime = 0x00000132,
 	HostComputer = 0x0000013c,
 	WhitePoint = 0x0000013e,
 	PrimaryChromaticities = 0x0000013f,
+	SubIFDs = 0x0000014a,
 	YCbCrCoefficients = 0x00000211,
+	JPEGInterchangeFormat = 0x00000201,
+	JPEGInterchangeFormatLength = 0x00000202,
 	ReferenceBlackWhite = 0x00000214,
 	Copyright = 0x00008298,
 	ExifOffset = 0x00008769,
@@ -124,6 +419,7 @@ pub enum ExifTag {
 	FlashPixVersion = 0x0000a000,
 	ColorSpace = 0x0000a001,
 	RelatedSoundFile = 0x0000a004,
+	InteropOffset = 0x0000a005,
 	FlashEnergy = 0x0000a20b,
 	FocalPlaneXResolution = 0x0000a20e,
 	FocalPlaneYResolution = 0x0000a20f,
@@ -183,6 +479,12 @@ pub enum ExifTag {
 	GPSAreaInformation = 0x00001c,
 	GPSDateStamp = 0x00001d,
 	GPSDifferential = 0x00001e,
+
+	InteroperabilityIndex = 0x00010001,
+	InteroperabilityVersion = 0x00010002,
+	RelatedImageFileFormat = 0x00011000,
+	RelatedImageWidth = 0x00011001,
+	RelatedImageLength = 0x00011002,
 }
 
 impl Eq for ExifTag {}
@@ -191,6 +493,20 @@ impl Eq for ExifTag {}
 impl fmt::Display for ExifTag {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		write!(f, "{}", match *self {
+			ExifTag::NewSubfileType => "Subfile type",
+			ExifTag::ImageWidth => "Image width",
+			ExifTag::ImageLength => "Image length",
+			ExifTag::BitsPerSample => "Bits per sample",
+			ExifTag::Compression => "Compression",
+			ExifTag::PhotometricInterpretation => "Photometric interpretation",
+			ExifTag::DocumentName => "Document name",
+			ExifTag::StripOffsets => "Strip offsets",
+			ExifTag::SamplesPerPixel => "Samples per pixel",
+			ExifTag::RowsPerStrip => "Rows per strip",
+			ExifTag::StripByteCounts => "Strip byte counts",
+			ExifTag::PlanarConfiguration => "Planar configuration",
+			ExifTag::PageName => "Page name",
+			ExifTag::PageNumber => "Page number",
 			ExifTag::ImageDescription => "Image Description",
 			ExifTag::Make => "Manufacturer",
 			ExifTag::HostComputer => "Host computer",
@@ -203,7 +519,10 @@ impl fmt::Display for ExifTag {
 			ExifTag::DateTime => "Image date",
 			ExifTag::WhitePoint => "White Point",
 			ExifTag::PrimaryChromaticities => "Primary Chromaticities",
+			ExifTag::SubIFDs => "This image has SubIFDs",
 			ExifTag::YCbCrCoefficients => "YCbCr Coefficients",
+			ExifTag::JPEGInterchangeFormat => "Offset of JPEG thumbnail",
+			ExifTag::JPEGInterchangeFormatLength => "Length of JPEG thumbnail",
 			ExifTag::ReferenceBlackWhite => "Reference Black/White",
 			ExifTag::Copyright => "Copyright",
 			ExifTag::ExifOffset => "This image has an Exif SubIFD",
@@ -235,6 +554,7 @@ impl fmt::Display for ExifTag {
 			ExifTag::ColorSpace => "Color space",
 			ExifTag::FlashEnergy => "Flash energy",
 			ExifTag::RelatedSoundFile => "Related sound file",
+			ExifTag::InteropOffset => "This image has an Interoperability IFD",
 			ExifTag::FocalPlaneXResolution => "Focal plane X resolution",
 			ExifTag::FocalPlaneYResolution => "Focal plane Y resolution",
 			ExifTag::FocalPlaneResolutionUnit => "Focal plane resolution unit",
@@ -292,6 +612,11 @@ impl fmt::Display for ExifTag {
 			ExifTag::GPSAreaInformation => "GPS area information",
 			ExifTag::GPSDateStamp => "GPS date stamp",
 			ExifTag::GPSDifferential => "GPS differential",
+			ExifTag::InteroperabilityIndex => "Interoperability index",
+			ExifTag::InteroperabilityVersion => "Interoperability version",
+			ExifTag::RelatedImageFileFormat => "Related image file format",
+			ExifTag::RelatedImageWidth => "Related image width",
+			ExifTag::RelatedImageLength => "Related image length",
 			ExifTag::UnknownToMe => "Unknown to this library, or manufacturer-specific",
 		})
 	}
@@ -316,6 +641,14 @@ pub enum IfdFormat {
 	IRational = 10,
 	F32 = 11,
 	F64 = 12,
+	/// Offset of an IFD, same as U32 but tells the data points to a sub-IFD
+	Ifd = 13,
+	/// BigTIFF unsigned 64-bit integer (LONG8)
+	U64 = 16,
+	/// BigTIFF signed 64-bit integer (SLONG8)
+	I64 = 17,
+	/// BigTIFF offset of an IFD, same as U64 but tells the data points to a sub-IFD
+	Ifd8 = 18,
 }
 
 /// Structure that represents a parsed EXIF tag.
@@ -332,6 +665,8 @@ pub struct ExifEntry {
 	/// tag in detail, and parsing will be incomplete. The client may read into
 	/// `ifd` to discover more about the unparsed tag.
 	pub tag: ExifTag,
+	/// IFD where the tag was found. The tag is interpreted according to this IFD.
+	pub ifd_kind: IfdKind,
 	/// EXIF tag value as an enumeration. Behaves as a "variant" value
 	pub value: TagValue,
 	/// Unit of the value, if applicable. If tag is `UnknownToMe`, unit will be empty.
@@ -379,6 +714,10 @@ pub enum TagValue {
 	I32(Vec<i32>),
 	/// Array of `IRational` structures (tuples with signed integer numerator and denominator)
 	IRational(Vec<IRational>),
+	/// Array of unsigned 64-bit integers (BigTIFF only)
+	U64(Vec<u64>),
+	/// Array of signed 64-bit integers (BigTIFF only)
+	I64(Vec<i64>),
 	/// Array of IEEE 754 floating-points
 	F32(Vec<f32>),
 	/// Array of IEEE 754 floating-points
@@ -410,6 +749,8 @@ impl TagValue {
 			TagValue::I8(ref v) => v.get(index).cloned().map(From::from),
 			TagValue::I16(ref v) => v.get(index).cloned().map(From::from),
 			TagValue::I32(ref v) => v.get(index).cloned().map(From::from),
+			TagValue::U64(ref v) => v.get(index).and_then(|&n| i64::try_from(n).ok()),
+			TagValue::I64(ref v) => v.get(index).cloned(),
 			_ => None,
 		}
 	}
@@ -424,8 +765,10 @@ impl TagValue {
 			TagValue::I8(ref v) => v.get(index).cloned().map(From::from),
 			TagValue::I16(ref v) => v.get(index).cloned().map(From::from),
 			TagValue::I32(ref v) => v.get(index).cloned().map(From::from),
+			TagValue::U64(ref v) => v.get(index).map(|&n| n as f64),
+			TagValue::I64(ref v) => v.get(index).map(|&n| n as f64),
 			TagValue::F32(ref v) => v.get(index).cloned().map(From::from),
-			TagValue::F64(ref v) => v.get(index).cloned().map(From::from),
+			TagValue::F64(ref v) => v.get(index).cloned(),
 			TagValue::IRational(ref v) => v.get(index).cloned().map(|v| v.value()),
 			TagValue::URational(ref v) => v.get(index).cloned().map(|v| v.value()),
 			_ => None,
@@ -438,3 +781,6 @@ pub type ExifResult = Result<ExifData, ExifError>;
 
 /// Type resturned by lower-level parsing functions
 pub type ExifEntryResult = Result<Vec<ExifEntry>, ExifError>;
+
+/// Type returned by the parsing of the whole IFD chain
+pub type IfdDirectoryResult = Result<Vec<IfdDirectory>, ExifError>;
@@ -4,6 +4,7 @@ use std::error::Error;
 use std::io;
 use super::types::*;
 use super::lowlevel::*;
+use super::source::ByteSource;
 use super::ifdformat::numarray_to_string;
 
 /// Convert an IFD format code to the IfdFormat enumeration
@@ -22,6 +23,10 @@ pub fn ifdformat_new(n: u16) -> IfdFormat
 		10 => IfdFormat::IRational,
 		11 => IfdFormat::F32,
 		12 => IfdFormat::F64,
+		13 => IfdFormat::Ifd,
+		16 => IfdFormat::U64,
+		17 => IfdFormat::I64,
+		18 => IfdFormat::Ifd8,
 		_ => IfdFormat::Unknown,
 	}
 }
@@ -31,7 +36,11 @@ impl IfdEntry {
 	/// The call can't fail, but the caller must be sure that the IFD entry uses
 	/// the IFD data area as an offset (i.e. when the tag is a Sub-IFD tag, or when
 	/// there are more than 4 bytes of data and it would not fit within IFD).
+	/// BigTIFF entries have 8 bytes of IFD data, and 64-bit offsets.
 	pub fn data_as_offset(&self) -> usize {
+		if self.ifd_data.len() >= 8 {
+			return read_u64(self.le, &(self.ifd_data[0..8])) as usize;
+		}
 		read_u32(self.le, &(self.ifd_data[0..4])) as usize
 	}
 
@@ -53,6 +62,10 @@ impl IfdEntry {
 			IfdFormat::IRational => 8,
 			IfdFormat::F32 => 4,
 			IfdFormat::F64 => 8,
+			IfdFormat::Ifd => 4,
+			IfdFormat::U64 => 8,
+			IfdFormat::I64 => 8,
+			IfdFormat::Ifd8 => 8,
 			IfdFormat::Unknown => 1,
 		}
 	}
@@ -60,7 +73,7 @@ impl IfdEntry {
 	/// Total length of the whole IFD entry (element count x element size)
 	pub fn length(&self) -> usize
 	{
-		(self.size() as usize) * (self.count as usize)
+		(self.size() as usize).saturating_mul(self.count as usize)
 	}
 
 	/// Returns true if data is contained within the IFD structure, false when
@@ -68,41 +81,107 @@ impl IfdEntry {
 	/// data offset, instead of data).
 	pub fn in_ifd(&self) -> bool
 	{
-		self.length() <= 4
+		self.length() <= self.ifd_data.len()
 	}
 
-	/// Copies data from IFD entry section reserved for data (up to 4 bytes), or
+	/// Copies data from IFD entry section reserved for data (up to 4 bytes, or
+	/// 8 bytes in BigTIFF), or
 	/// from another part of the image file (when data wouldn't fit in IFD structure).
 	/// In either case, the data member will contain the data of interest after
 	/// this call.
 	pub fn copy_data(&mut self, contents: &[u8]) -> bool
+	{
+		let mut contents = contents;
+		self.copy_data_from(&mut contents)
+	}
+
+	/// Same as `copy_data`, but reads only the out-of-line data block from the source
+	pub(crate) fn copy_data_from<S: ByteSource>(&mut self, contents: &mut S) -> bool
 	{
 		if self.in_ifd() {
-			// the 4 bytes from IFD have all data
+			// the 4 (or 8) bytes from IFD have all data
 			self.data = self.ifd_data.clone();
 			return true;
 		}
 
 		let offset = self.data_as_offset();
-		if contents.len() < (offset + self.length()) {
-			// println!("EXIF data block goes beyond EOF");
-			return false;
-		}
+		let ext_data = match contents.read_at(offset, self.length()) {
+			Some(ext_data) => ext_data,
+			None => {
+				// println!("EXIF data block goes beyond EOF");
+				return false;
+			}
+		};
 
-		let ext_data = &contents[offset..(offset + self.length())];
-		self.ext_data.clear();	
-		self.ext_data.extend(ext_data);
+		self.ext_data = ext_data;
 		self.data = self.ext_data.clone();
 		return true;
 	}
 }
 
+impl ExifData {
+	/// Returns the bytes of the thumbnail embedded in the image, as described by IFD1.
+	///
+	/// Most of the time it is a complete JPEG stream. For uncompressed thumbnails
+	/// (typical of TIFF files), the strips are returned concatenated, and the
+	/// client must interpret them using the other IFD1 entries (dimensions,
+	/// photometric interpretation, etc.)
+	pub fn thumbnail(&self) -> Option<&[u8]> {
+		self.thumbnail.as_ref().map(|t| &t[..])
+	}
+
+	/// Returns the value of a top-level XMP property, given its namespace URI
+	/// and its name without prefix (e.g. "http://ns.adobe.com/xap/1.0/" and "Rating")
+	pub fn xmp_property(&self, namespace: &str, name: &str) -> Option<&XmpValue> {
+		self.xmp.iter()
+			.find(|p| p.namespace == namespace && p.name == name)
+			.map(|p| &p.value)
+	}
+
+	/// Returns the readable values of an IPTC dataset, given its record and
+	/// dataset numbers (e.g. 2 and 25 for Keywords), in file order
+	pub fn iptc_values(&self, record: u8, dataset: u8) -> Vec<&str> {
+		self.iptc.iter()
+			.filter(|d| d.record == record && d.dataset == dataset)
+			.map(|d| &d.value[..])
+			.collect()
+	}
+}
+
+impl Display for ExifWarning {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?} IFD, tag {:x}, offset {}: ", self.ifd_kind, self.tag, self.offset)?;
+		match self.kind {
+			ExifWarningKind::WrongFormat(expected, found) =>
+				write!(f, "expected format {} ({:?}), found {} ({:?})",
+					expected as u16, expected, found as u16, found),
+			ExifWarningKind::FormatCoerced(expected, found) =>
+				write!(f, "format {} ({:?}) converted to {} ({:?})",
+					found as u16, found, expected as u16, expected),
+			ExifWarningKind::CountOutOfRange(min, max, found) =>
+				write!(f, "expected count {}..{}, found {}", min, max, found),
+			ExifWarningKind::DataBeyondEof => write!(f, "data goes beyond EOF"),
+			ExifWarningKind::IfdTruncated(ref s) => write!(f, "IFD skipped: {}", s),
+		}
+	}
+}
+
 impl Error for ExifError {
 	fn description(&self) -> &str {
 		match *self {
-			ExifError::IoError(ref e) => e.description(),
+			ExifError::IoError(_) => "I/O error",
 			ExifError::FileTypeUnknown => "File type unknown",
 			ExifError::JpegWithoutExif(_) => "JPEG without EXIF section",
+			ExifError::PngWithoutExif(_) => "PNG without EXIF chunk",
+			ExifError::WebpWithoutExif(_) => "WebP without EXIF chunk",
+			ExifError::HeifWithoutExif(_) => "HEIF without Exif item",
+			ExifError::JxlWithoutExif(_) => "JPEG XL without Exif box",
+			ExifError::Cr3WithoutExif(_) => "CR3 without metadata boxes",
+			ExifError::RafWithoutExif(_) => "RAF without JPEG preview",
+			ExifError::PsdWithoutExif(_) => "PSD without EXIF resource",
+			ExifError::XmpMalformed(_) => "Malformed XMP packet",
+			ExifError::IptcMalformed(_) => "Malformed IPTC record",
+			ExifError::IrbMalformed(_) => "Malformed Photoshop resource block",
 			ExifError::TiffTruncated => "TIFF truncated at start",
 			ExifError::TiffBadPreamble(_) => "TIFF with bad preamble",
 			ExifError::IfdTruncated => "TIFF IFD truncated",
@@ -118,6 +197,16 @@ impl Display for ExifError {
 			ExifError::IoError(ref e) => e.fmt(f),
 			ExifError::FileTypeUnknown => write!(f, "File type unknown"),
 			ExifError::JpegWithoutExif(ref s) => write!(f, "JPEG without EXIF section: {}", s),
+			ExifError::PngWithoutExif(ref s) => write!(f, "PNG without EXIF chunk: {}", s),
+			ExifError::WebpWithoutExif(ref s) => write!(f, "WebP without EXIF chunk: {}", s),
+			ExifError::HeifWithoutExif(ref s) => write!(f, "HEIF without Exif item: {}", s),
+			ExifError::JxlWithoutExif(ref s) => write!(f, "JPEG XL without Exif box: {}", s),
+			ExifError::Cr3WithoutExif(ref s) => write!(f, "CR3 without metadata boxes: {}", s),
+			ExifError::RafWithoutExif(ref s) => write!(f, "RAF without JPEG preview: {}", s),
+			ExifError::PsdWithoutExif(ref s) => write!(f, "PSD without EXIF resource: {}", s),
+			ExifError::XmpMalformed(ref s) => write!(f, "Malformed XMP packet: {}", s),
+			ExifError::IptcMalformed(ref s) => write!(f, "Malformed IPTC record: {}", s),
+			ExifError::IrbMalformed(ref s) => write!(f, "Malformed Photoshop resource block: {}", s),
 			ExifError::TiffTruncated => write!(f, "TIFF truncated at start"),
 			ExifError::TiffBadPreamble(ref s) => write!(f, "TIFF with bad preamble: {}", s),
 			ExifError::IfdTruncated => write!(f, "TIFF IFD truncated"),
@@ -143,6 +232,8 @@ impl fmt::Display for TagValue {
 			TagValue::I8(ref a) => write!(f, "{}", numarray_to_string(a)),
 			TagValue::U32(ref a) => write!(f, "{}", numarray_to_string(a)),
 			TagValue::I32(ref a) => write!(f, "{}", numarray_to_string(a)),
+			TagValue::U64(ref a) => write!(f, "{}", numarray_to_string(a)),
+			TagValue::I64(ref a) => write!(f, "{}", numarray_to_string(a)),
 			TagValue::F32(ref a) => write!(f, "{}", numarray_to_string(a)),
 			TagValue::F64(ref a) => write!(f, "{}", numarray_to_string(a)),
 			TagValue::URational(ref a) => write!(f, "{}", numarray_to_string(a)),
@@ -153,3 +244,25 @@ impl fmt::Display for TagValue {
 		}
 	}
 }
+
+impl fmt::Display for XmpValue {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			XmpValue::Simple(ref s) => write!(f, "{}", s),
+			XmpValue::Bag(ref a) => write!(f, "{}", numarray_to_string(a)),
+			XmpValue::Seq(ref a) => write!(f, "{}", numarray_to_string(a)),
+			XmpValue::Alt(ref a) => write!(f, "{}", numarray_to_string(a)),
+			XmpValue::LangAlt(ref a) => {
+				// the default language, or else the first one
+				let text = a.iter().find(|&(lang, _)| lang == "x-default").or_else(|| a.first());
+				write!(f, "{}", text.map(|(_, text)| &text[..]).unwrap_or(""))
+			},
+			XmpValue::Struct(ref fields) => {
+				let fields: Vec<String> = fields.iter()
+					.map(|p| format!("{}:{}={}", p.prefix, p.name, p.value))
+					.collect();
+				write!(f, "{{{}}}", fields.join("; "))
+			},
+		}
+	}
+}
@@ -0,0 +1,130 @@
+use super::types::ExifError;
+use super::lowlevel::read_u32;
+use super::source::ByteSource;
+
+/// Find a chunk of the RIFF container of a WebP image, e.g. "EXIF".
+/// Returns offset and size of the chunk data.
+fn find_webp_chunk<S: ByteSource>(contents: &mut S, fourcc: &[u8])
+					-> Result<(usize, usize), ExifError>
+{
+	let name = String::from_utf8_lossy(fourcc);
+
+	let header = match contents.read_at(0, 12) {
+		Some(header) => header,
+		None => return Err(ExifError::WebpWithoutExif("RIFF header truncated".to_string())),
+	};
+
+	// the RIFF size does not include the first 8 bytes
+	let end = (read_u32(true, &header[4..8]) as usize).saturating_add(8).min(contents.size());
+	let mut offset: usize = 12;
+	let mut first = true;
+
+	while offset < end {
+		let chunk = match contents.read_at(offset, 8) {
+			Some(chunk) => chunk,
+			None => return Err(ExifError::WebpWithoutExif("WebP truncated in chunk header".to_string())),
+		};
+		let size = read_u32(true, &chunk[4..8]) as usize;
+		offset += 8;
+
+		if first && chunk[0..4] != b"VP8X"[..] {
+			// Simple format (a lone VP8 or VP8L chunk) has no room for metadata
+			return Err(ExifError::WebpWithoutExif("Simple WebP format without VP8X chunk".to_string()));
+		}
+		first = false;
+
+		// The EXIF and XMP flags of VP8X are not checked, because some encoders fail to set them.
+
+		if chunk[0..4] == *fourcc {
+			if offset.saturating_add(size) > contents.size() {
+				return Err(ExifError::WebpWithoutExif(format!("WebP truncated in {} chunk", name.trim_end())));
+			}
+			return Ok((offset, size));
+		}
+
+		// chunks are padded to an even size
+		offset = offset.saturating_add(size).saturating_add(size & 1);
+	}
+
+	return Err(ExifError::WebpWithoutExif(format!("No {} chunk found", name.trim_end())));
+}
+
+/// Find the EXIF data (a TIFF block) in a WebP image, stored in the EXIF chunk
+/// of the RIFF container. The leading "Exif\0\0" that some encoders write is
+/// skipped. Returns offset and size of the TIFF block.
+pub fn find_embedded_tiff_in_webp<S: ByteSource>(contents: &mut S)
+					-> Result<(usize, usize), ExifError>
+{
+	let (offset, size) = find_webp_chunk(contents, b"EXIF")?;
+
+	if size >= 6 {
+		if let Some(prefix) = contents.read_at(offset, 6) {
+			if prefix[..] == b"Exif\0\0"[..] {
+				return Ok((offset + 6, size - 6));
+			}
+		}
+	}
+	return Ok((offset, size));
+}
+
+/// Find the XMP packet of a WebP image, stored in the "XMP " chunk of the RIFF
+/// container. Returns None if there is no such chunk.
+pub fn find_xmp_in_webp<S: ByteSource>(contents: &mut S) -> Option<Vec<u8>>
+{
+	let (offset, size) = find_webp_chunk(contents, b"XMP ").ok()?;
+	return contents.read_at(offset, size);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testutil::*;
+
+	/// WebP image made of the given chunks, padded to an even size
+	fn webp(chunks: &[(&[u8], &[u8])]) -> Vec<u8>
+	{
+		let mut data = b"WEBP".to_vec();
+		for &(fourcc, chunk) in chunks {
+			data.extend_from_slice(fourcc);
+			data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+			data.extend_from_slice(chunk);
+			if chunk.len() & 1 != 0 {
+				data.push(0);
+			}
+		}
+		let mut riff = b"RIFF".to_vec();
+		riff.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		riff.extend(data);
+		return riff;
+	}
+
+	#[test]
+	fn exif_chunk() {
+		let tiff = sample_exif(true);
+		let prefixed = [&b"Exif\0\0"[..], &tiff].concat();
+		for exif in &[&tiff, &prefixed] {
+			// odd-sized ICC profile before the EXIF chunk
+			let image = webp(&[(b"VP8X", &[0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0]), (b"ICCP", b"icc"),
+				(b"VP8 ", b"image"), (b"EXIF", exif)]);
+			let (offset, size) = find_embedded_tiff_in_webp(&mut &image[..]).unwrap();
+			assert_eq!(&image[offset..offset + size], &tiff[..]);
+
+			let d = ::parse_buffer(&image).unwrap();
+			assert_eq!(d.mime, "image/webp");
+			assert!(d.entries.iter().any(|e| e.tag == ::ExifTag::Model));
+		}
+	}
+
+	#[test]
+	fn no_exif_chunk() {
+		let simple = webp(&[(b"VP8L", b"image")]);
+		assert!(find_embedded_tiff_in_webp(&mut &simple[..]).is_err());
+
+		let extended = webp(&[(b"VP8X", &[0; 10]), (b"VP8 ", b"image")]);
+		assert!(find_embedded_tiff_in_webp(&mut &extended[..]).is_err());
+
+		let mut truncated = webp(&[(b"VP8X", &[0; 10]), (b"EXIF", &sample_exif(true))]);
+		truncated.truncate(truncated.len() - 10);
+		assert!(find_embedded_tiff_in_webp(&mut &truncated[..]).is_err());
+	}
+}
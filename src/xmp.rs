@@ -0,0 +1,614 @@
+use super::types::*;
+use super::source::ByteSource;
+use super::jpeg::{find_xmp_in_jpeg, find_extended_xmp_in_jpeg};
+use super::png::find_xmp_in_png;
+use super::webp::find_xmp_in_webp;
+use super::psd::find_xmp_in_psd;
+use super::heif::find_xmp_in_heif;
+use super::jxl::find_xmp_in_jxl;
+use super::cr3::find_xmp_in_cr3;
+use super::raf::find_xmp_in_raf;
+
+/// Namespace of the RDF syntax elements and attributes
+const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+
+/// Namespace of the "xml" prefix, e.g. xml:lang
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Namespace of xmpNote:HasExtendedXMP
+const XMP_NOTE_NS: &str = "http://ns.adobe.com/xmp/note/";
+
+/// Largest XMP packet accepted
+pub const MAX_XMP_SIZE: usize = 16 * 1024 * 1024;
+
+/// Maximum nesting level of XML elements. Real-world packets use less than 10.
+const MAX_DEPTH: usize = 64;
+
+/// Attribute of an XML element, with the prefix resolved to a namespace URI
+struct XmlAttribute {
+	namespace: String,
+	prefix: String,
+	name: String,
+	value: String,
+}
+
+/// Element of an XML document, with the prefix resolved to a namespace URI.
+/// The text of mixed content is concatenated.
+struct XmlElement {
+	namespace: String,
+	prefix: String,
+	name: String,
+	attributes: Vec<XmlAttribute>,
+	children: Vec<XmlElement>,
+	text: String,
+}
+
+impl XmlElement {
+	fn is(&self, namespace: &str, name: &str) -> bool
+	{
+		self.namespace == namespace && self.name == name
+	}
+
+	fn attribute(&self, namespace: &str, name: &str) -> Option<&str>
+	{
+		self.attributes.iter()
+			.find(|a| a.namespace == namespace && a.name == name)
+			.map(|a| &a.value[..])
+	}
+}
+
+fn xml_error(s: &str) -> ExifError
+{
+	ExifError::XmpMalformed(s.to_string())
+}
+
+/// Longest entity or character reference that is decoded, e.g. "&#x10FFFF;"
+const MAX_ENTITY_SIZE: usize = 10;
+
+/// Replaces the predefined entities and the character references of a text.
+/// Unknown entities, and ampersands not followed by a reference, are kept as
+/// they are.
+fn decode_entities(text: &str) -> String
+{
+	let mut decoded = String::with_capacity(text.len());
+	let mut rest = text;
+
+	while let Some(amp) = rest.find('&') {
+		decoded.push_str(&rest[..amp]);
+		rest = &rest[amp..];
+
+		// the semicolon is only looked for nearby, so that a long text full of
+		// ampersands takes linear time
+		let window = &rest.as_bytes()[..rest.len().min(MAX_ENTITY_SIZE)];
+		let entity = match window.iter().position(|&c| c == b';') {
+			Some(semicolon) => &rest[1..semicolon],
+			None => "",
+		};
+		let c = match entity {
+			"lt" => Some('<'),
+			"gt" => Some('>'),
+			"amp" => Some('&'),
+			"quot" => Some('"'),
+			"apos" => Some('\''),
+			_ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(std::char::from_u32),
+			_ if entity.starts_with('#') => entity[1..].parse().ok().and_then(std::char::from_u32),
+			_ => None,
+		};
+
+		match c {
+			Some(c) => {
+				decoded.push(c);
+				rest = &rest[entity.len() + 2..];
+			},
+			None => {
+				decoded.push('&');
+				rest = &rest[1..];
+			}
+		}
+	}
+
+	decoded.push_str(rest);
+	return decoded;
+}
+
+/// Minimal XML parser, enough for XMP packets: elements, attributes, namespaces,
+/// text, CDATA sections, comments and processing instructions. DTDs are skipped.
+struct XmlParser<'a> {
+	text: &'a str,
+	pos: usize,
+	/// Namespace declarations in scope, as (prefix, URI), innermost last
+	namespaces: Vec<(String, String)>,
+}
+
+impl<'a> XmlParser<'a> {
+	fn rest(&self) -> &'a str
+	{
+		&self.text[self.pos..]
+	}
+
+	fn skip_whitespace(&mut self)
+	{
+		let rest = self.rest();
+		self.pos += rest.len() - rest.trim_start().len();
+	}
+
+	/// Skips up to and including `end`
+	fn skip_past(&mut self, end: &str) -> Result<(), ExifError>
+	{
+		match self.rest().find(end) {
+			Some(n) => {
+				self.pos += n + end.len();
+				Ok(())
+			},
+			None => Err(xml_error(&format!("Missing {}", end))),
+		}
+	}
+
+	/// Skips comments, processing instructions (e.g. <?xpacket?>) and DTDs.
+	/// Returns false if there was nothing to skip.
+	fn skip_misc(&mut self) -> Result<bool, ExifError>
+	{
+		if self.rest().starts_with("<!--") {
+			self.skip_past("-->")?;
+		} else if self.rest().starts_with("<?") {
+			self.skip_past("?>")?;
+		} else if self.rest().starts_with("<!") && !self.rest().starts_with("<![CDATA[") {
+			self.skip_past(">")?;
+		} else {
+			return Ok(false);
+		}
+		return Ok(true);
+	}
+
+	fn read_name(&mut self) -> Result<&'a str, ExifError>
+	{
+		let rest = self.rest();
+		let end = rest.find(|c: char| c.is_whitespace() || c == '=' || c == '/' || c == '>')
+			.unwrap_or(rest.len());
+		if end == 0 {
+			return Err(xml_error("Name expected"));
+		}
+		self.pos += end;
+		return Ok(&rest[..end]);
+	}
+
+	/// Splits a qualified name and resolves its prefix. Unprefixed attributes
+	/// have no namespace, unprefixed elements take the default namespace.
+	fn resolve(&self, qname: &str, element: bool) -> (String, String, String)
+	{
+		let (prefix, name) = match qname.find(':') {
+			Some(colon) => (&qname[..colon], &qname[colon + 1..]),
+			None => ("", qname),
+		};
+
+		let namespace = if prefix == "xml" {
+			XML_NS.to_string()
+		} else if prefix.is_empty() && !element {
+			"".to_string()
+		} else {
+			self.namespaces.iter().rev()
+				.find(|&(p, _)| p == prefix)
+				.map(|(_, uri)| uri.clone())
+				.unwrap_or_default()
+		};
+
+		return (namespace, prefix.to_string(), name.to_string());
+	}
+
+	/// Parses an element and its contents, starting at its "<"
+	fn parse_element(&mut self, depth: usize) -> Result<XmlElement, ExifError>
+	{
+		if depth > MAX_DEPTH {
+			return Err(xml_error("Elements nested too deep"));
+		}
+
+		self.pos += 1;
+		let qname = self.read_name()?;
+		let scope = self.namespaces.len();
+		let mut raw_attributes: Vec<(&str, String)> = Vec::new();
+
+		let empty = loop {
+			self.skip_whitespace();
+			if self.rest().starts_with("/>") {
+				self.pos += 2;
+				break true;
+			}
+			if self.rest().starts_with('>') {
+				self.pos += 1;
+				break false;
+			}
+
+			let name = self.read_name()?;
+			self.skip_whitespace();
+			if !self.rest().starts_with('=') {
+				return Err(xml_error("Attribute without value"));
+			}
+			self.pos += 1;
+			self.skip_whitespace();
+
+			let quote = match self.rest().chars().next() {
+				Some(quote) if quote == '"' || quote == '\'' => quote,
+				_ => return Err(xml_error("Attribute value without quotes")),
+			};
+			self.pos += 1;
+			let end = self.rest().find(quote).ok_or_else(|| xml_error("Unterminated attribute value"))?;
+			let value = decode_entities(&self.rest()[..end]);
+			self.pos += end + 1;
+
+			if name == "xmlns" {
+				self.namespaces.push(("".to_string(), value));
+			} else if let Some(prefix) = name.strip_prefix("xmlns:") {
+				self.namespaces.push((prefix.to_string(), value));
+			} else {
+				raw_attributes.push((name, value));
+			}
+		};
+
+		let (namespace, prefix, name) = self.resolve(qname, true);
+		let attributes = raw_attributes.into_iter().map(|(qname, value)| {
+			let (namespace, prefix, name) = self.resolve(qname, false);
+			XmlAttribute { namespace, prefix, name, value }
+		}).collect();
+
+		let mut element = XmlElement {
+			namespace,
+			prefix,
+			name,
+			attributes,
+			children: Vec::new(),
+			text: String::new(),
+		};
+
+		if !empty {
+			loop {
+				if self.rest().is_empty() {
+					return Err(xml_error(&format!("Unterminated element {}", qname)));
+				} else if self.rest().starts_with("</") {
+					self.pos += 2;
+					if self.read_name()? != qname {
+						return Err(xml_error(&format!("Mismatched end of element {}", qname)));
+					}
+					self.skip_past(">")?;
+					break;
+				} else if self.rest().starts_with("<![CDATA[") {
+					self.pos += 9;
+					let end = self.rest().find("]]>").ok_or_else(|| xml_error("Unterminated CDATA section"))?;
+					element.text.push_str(&self.rest()[..end]);
+					self.pos += end + 3;
+				} else if self.skip_misc()? {
+					// comment or processing instruction
+				} else if self.rest().starts_with('<') {
+					let child = self.parse_element(depth + 1)?;
+					element.children.push(child);
+				} else {
+					let end = self.rest().find('<').unwrap_or(self.rest().len());
+					element.text.push_str(&decode_entities(&self.rest()[..end]));
+					self.pos += end;
+				}
+			}
+		}
+
+		self.namespaces.truncate(scope);
+		return Ok(element);
+	}
+
+	/// Parses a whole document, returns its root element
+	fn parse_document(&mut self) -> Result<XmlElement, ExifError>
+	{
+		loop {
+			self.skip_whitespace();
+			if self.rest().is_empty() {
+				return Err(xml_error("No root element"));
+			}
+			if !self.skip_misc()? {
+				break;
+			}
+		}
+
+		if !self.rest().starts_with('<') {
+			return Err(xml_error("Text outside of root element"));
+		}
+		return self.parse_element(0);
+	}
+}
+
+/// Finds the rdf:RDF element, normally a child of x:xmpmeta
+fn find_rdf(element: &XmlElement) -> Option<&XmlElement>
+{
+	if element.is(RDF_NS, "RDF") {
+		return Some(element);
+	}
+	element.children.iter().filter_map(find_rdf).next()
+}
+
+/// Makes a property out of an element or attribute name
+fn property(namespace: &str, prefix: &str, name: &str, value: XmpValue) -> XmpProperty
+{
+	XmpProperty {
+		namespace: namespace.to_string(),
+		prefix: prefix.to_string(),
+		name: name.to_string(),
+		value,
+	}
+}
+
+/// Lists the properties of an rdf:Description element, or the fields of a
+/// structure: attributes are simple properties, child elements are properties
+/// of any kind. RDF and XML attributes (rdf:about, xml:lang...) are not properties.
+fn description_properties(element: &XmlElement) -> Vec<XmpProperty>
+{
+	let mut properties: Vec<XmpProperty> = Vec::new();
+
+	for a in &element.attributes {
+		if a.namespace.is_empty() || a.namespace == RDF_NS || a.namespace == XML_NS {
+			continue;
+		}
+		properties.push(property(&a.namespace, &a.prefix, &a.name, XmpValue::Simple(a.value.clone())));
+	}
+
+	for child in &element.children {
+		if child.namespace == RDF_NS {
+			continue;
+		}
+		properties.push(property(&child.namespace, &child.prefix, &child.name, property_value(child)));
+	}
+
+	return properties;
+}
+
+/// Value of a property element (or of an array item), in any of the forms
+/// allowed by the XMP serialization of RDF
+fn property_value(element: &XmlElement) -> XmpValue
+{
+	if let Some(resource) = element.attribute(RDF_NS, "resource") {
+		// URI value
+		return XmpValue::Simple(resource.to_string());
+	}
+	if element.attribute(RDF_NS, "parseType") == Some("Resource") {
+		// structure without rdf:Description
+		return struct_value(element);
+	}
+
+	if let Some(child) = element.children.first() {
+		if child.is(RDF_NS, "Bag") || child.is(RDF_NS, "Seq") || child.is(RDF_NS, "Alt") {
+			return array_value(child);
+		}
+		if child.is(RDF_NS, "Description") {
+			return struct_value(child);
+		}
+		// not quite valid RDF, taken as a structure
+		return XmpValue::Struct(description_properties(element));
+	}
+
+	let has_fields = element.attributes.iter()
+		.any(|a| !a.namespace.is_empty() && a.namespace != RDF_NS && a.namespace != XML_NS);
+	if has_fields {
+		// structure whose fields are all attributes
+		return XmpValue::Struct(description_properties(element));
+	}
+
+	return XmpValue::Simple(element.text.clone());
+}
+
+/// Value of a structure. A structure with rdf:value is actually a simple value
+/// with qualifiers, that are dropped.
+fn struct_value(element: &XmlElement) -> XmpValue
+{
+	if let Some(value) = element.attribute(RDF_NS, "value") {
+		return XmpValue::Simple(value.to_string());
+	}
+	if let Some(value) = element.children.iter().find(|c| c.is(RDF_NS, "value")) {
+		return property_value(value);
+	}
+	return XmpValue::Struct(description_properties(element));
+}
+
+/// Value of an rdf:Bag, rdf:Seq or rdf:Alt element. An rdf:Alt whose items
+/// all have a language is a language alternative.
+fn array_value(array: &XmlElement) -> XmpValue
+{
+	let items: Vec<&XmlElement> = array.children.iter()
+		.filter(|c| c.is(RDF_NS, "li"))
+		.collect();
+
+	if array.name == "Alt" && !items.is_empty() &&
+			items.iter().all(|li| li.attribute(XML_NS, "lang").is_some()) {
+		return XmpValue::LangAlt(items.iter()
+			.map(|li| (li.attribute(XML_NS, "lang").unwrap_or_default().to_string(), li.text.clone()))
+			.collect());
+	}
+
+	let values: Vec<XmpValue> = items.into_iter().map(property_value).collect();
+	match &array.name[..] {
+		"Bag" => XmpValue::Bag(values),
+		"Seq" => XmpValue::Seq(values),
+		_ => XmpValue::Alt(values),
+	}
+}
+
+/// Parses an XMP packet, i.e. RDF/XML normally wrapped in x:xmpmeta and
+/// <?xpacket?> instructions, and returns its top-level properties, from
+/// every rdf:Description.
+pub fn parse_xmp(packet: &[u8]) -> Result<Vec<XmpProperty>, ExifError>
+{
+	if packet.len() > MAX_XMP_SIZE {
+		return Err(xml_error("Packet too big"));
+	}
+
+	let text = String::from_utf8_lossy(packet);
+	let text = text.trim_start_matches('\u{feff}');
+
+	let mut parser = XmlParser { text, pos: 0, namespaces: Vec::new() };
+	let root = parser.parse_document()?;
+	let rdf = find_rdf(&root).ok_or_else(|| xml_error("No rdf:RDF element"))?;
+
+	let mut properties: Vec<XmpProperty> = Vec::new();
+	for description in rdf.children.iter().filter(|c| c.is(RDF_NS, "Description")) {
+		properties.append(&mut description_properties(description));
+	}
+
+	return Ok(properties);
+}
+
+/// Parses the XMP packet of TIFF tag 700 (XMLPacket) in IFD0. Returns no
+/// properties if there is no packet, or if it is broken.
+pub fn xmp_from_entries(entries: &[ExifEntry]) -> Vec<XmpProperty>
+{
+	entries.iter()
+		.find(|e| e.ifd_kind == IfdKind::Ifd0 && e.ifd.tag == 700)
+		.and_then(|e| parse_xmp(&e.ifd.data).ok())
+		.unwrap_or_default()
+}
+
+/// Merges the extended XMP packet of a JPEG image into the properties of the
+/// main packet, when the main packet refers to it by xmpNote:HasExtendedXMP.
+/// A missing or broken extended packet is ignored.
+fn merge_extended_xmp<S: ByteSource>(contents: &mut S, properties: &mut Vec<XmpProperty>)
+{
+	let guid = match properties.iter().find(|p| p.namespace == XMP_NOTE_NS && p.name == "HasExtendedXMP") {
+		Some(&XmpProperty { value: XmpValue::Simple(ref guid), .. }) => guid.trim().to_string(),
+		_ => return,
+	};
+
+	let extended = find_extended_xmp_in_jpeg(contents, &guid)
+		.and_then(|packet| parse_xmp(&packet).ok());
+	if let Some(mut extended) = extended {
+		properties.append(&mut extended);
+	}
+}
+
+/// Finds the XMP packet that an image of a given type stores outside of the
+/// TIFF block (JPEG APP1 segment, PNG iTXt chunk, WebP "XMP " chunk, PSD image
+/// resource, HEIF "mime" item, JPEG XL "xml " box, CR3 "uuid" box, APP1 segment
+/// of the JPEG preview of RAF), and parses it. The extended XMP of JPEG images is merged in.
+/// Returns None if there is no packet, or if it is broken.
+pub fn read_container_xmp<S: ByteSource>(mime: &str, contents: &mut S) -> Option<Vec<XmpProperty>>
+{
+	let packet = match mime {
+		"image/jpeg" => find_xmp_in_jpeg(contents),
+		"image/png" => find_xmp_in_png(contents),
+		"image/webp" => find_xmp_in_webp(contents),
+		"image/vnd.adobe.photoshop" => find_xmp_in_psd(contents),
+		"image/heif" | "image/heic" | "image/avif" => find_xmp_in_heif(contents),
+		"image/jxl" => find_xmp_in_jxl(contents),
+		"image/x-canon-cr3" => find_xmp_in_cr3(contents),
+		"image/x-fuji-raf" => find_xmp_in_raf(contents),
+		_ => None,
+	};
+
+	let mut properties = parse_xmp(&packet?).ok()?;
+	if mime == "image/jpeg" {
+		merge_extended_xmp(contents, &mut properties);
+	}
+
+	return Some(properties);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Wraps the contents of a single rdf:Description in a packet
+	fn packet(attributes: &str, properties: &str) -> Vec<u8>
+	{
+		format!("<?xpacket begin=\"\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+			<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\
+			<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+			<rdf:Description rdf:about=\"\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" \
+			xmlns:dc=\"http://purl.org/dc/elements/1.1/\" {}>{}</rdf:Description>\
+			</rdf:RDF></x:xmpmeta><?xpacket end=\"w\"?>", attributes, properties).into_bytes()
+	}
+
+	/// Parses a packet and gets the value of its only property
+	fn single(properties: &str) -> XmpValue
+	{
+		let mut parsed = parse_xmp(&packet("", properties)).unwrap();
+		assert_eq!(parsed.len(), 1);
+		return parsed.remove(0).value;
+	}
+
+	fn simple(text: &str) -> XmpValue
+	{
+		XmpValue::Simple(text.to_string())
+	}
+
+	#[test]
+	fn simple_properties() {
+		let parsed = parse_xmp(&packet("xmp:Rating=\"4\"", "<dc:format>image/jpeg</dc:format>")).unwrap();
+		assert_eq!(parsed.len(), 2);
+		assert_eq!(parsed[0].namespace, "http://ns.adobe.com/xap/1.0/");
+		assert_eq!(parsed[0].prefix, "xmp");
+		assert_eq!(parsed[0].name, "Rating");
+		assert_eq!(parsed[0].value, simple("4"));
+		assert_eq!(parsed[1].namespace, "http://purl.org/dc/elements/1.1/");
+		assert_eq!(parsed[1].name, "format");
+		assert_eq!(parsed[1].value, simple("image/jpeg"));
+
+		assert_eq!(single("<xmp:BaseURL rdf:resource=\"http://example.com/\"/>"), simple("http://example.com/"));
+		assert!(parse_xmp(&packet("", "")).unwrap().is_empty());
+	}
+
+	#[test]
+	fn struct_properties() {
+		let fields = |value: XmpValue| match value {
+			XmpValue::Struct(fields) => fields.into_iter().map(|f| (f.name, f.value)).collect::<Vec<_>>(),
+			value => panic!("not a struct: {:?}", value),
+		};
+		let expected = vec![("a".to_string(), simple("1")), ("b".to_string(), simple("2"))];
+
+		assert_eq!(fields(single("<xmp:S rdf:parseType=\"Resource\"><xmp:a>1</xmp:a><xmp:b>2</xmp:b></xmp:S>")), expected);
+		assert_eq!(fields(single("<xmp:S><rdf:Description xmp:a=\"1\"><xmp:b>2</xmp:b></rdf:Description></xmp:S>")), expected);
+		assert_eq!(fields(single("<xmp:S xmp:a=\"1\" xmp:b=\"2\"/>")), expected);
+	}
+
+	#[test]
+	fn arrays() {
+		assert_eq!(single("<dc:subject><rdf:Bag><rdf:li>a</rdf:li><rdf:li>b</rdf:li></rdf:Bag></dc:subject>"),
+			XmpValue::Bag(vec![simple("a"), simple("b")]));
+		assert_eq!(single("<dc:creator><rdf:Seq><rdf:li>a</rdf:li></rdf:Seq></dc:creator>"),
+			XmpValue::Seq(vec![simple("a")]));
+		assert_eq!(single("<xmp:A><rdf:Alt><rdf:li>a</rdf:li><rdf:li>b</rdf:li></rdf:Alt></xmp:A>"),
+			XmpValue::Alt(vec![simple("a"), simple("b")]));
+		assert_eq!(single("<dc:subject><rdf:Bag/></dc:subject>"), XmpValue::Bag(vec![]));
+		assert_eq!(single("<xmp:A><rdf:Seq><rdf:li><rdf:Bag><rdf:li>a</rdf:li></rdf:Bag></rdf:li></rdf:Seq></xmp:A>"),
+			XmpValue::Seq(vec![XmpValue::Bag(vec![simple("a")])]));
+	}
+
+	#[test]
+	fn lang_alt() {
+		assert_eq!(single("<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">Sea</rdf:li>\
+				<rdf:li xml:lang=\"fr-FR\">Mer</rdf:li></rdf:Alt></dc:title>"),
+			XmpValue::LangAlt(vec![("x-default".to_string(), "Sea".to_string()),
+				("fr-FR".to_string(), "Mer".to_string())]));
+	}
+
+	#[test]
+	fn text() {
+		assert_eq!(single("<xmp:T>a &lt;b&gt; &amp; &#x263C;</xmp:T>"), simple("a <b> & \u{263c}"));
+		assert_eq!(single("<xmp:T><![CDATA[<red> & &amp;]]></xmp:T>"), simple("<red> & &amp;"));
+		assert_eq!(single("<xmp:T>a<!-- comment --> b</xmp:T>"), simple("a b"));
+	}
+
+	#[test]
+	fn malformed() {
+		assert!(parse_xmp(b"").is_err());
+		assert!(parse_xmp(b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF").is_err());
+		let deep = "<xmp:A rdf:parseType=\"Resource\">".repeat(MAX_DEPTH) + &"</xmp:A>".repeat(MAX_DEPTH);
+		assert!(parse_xmp(&packet("", &deep)).is_err());
+	}
+
+	#[test]
+	fn entities() {
+		assert_eq!(decode_entities("a &lt;b&gt; &amp; &quot;c&quot; &apos;d&apos;"), "a <b> & \"c\" 'd'");
+		assert_eq!(decode_entities("&#169; &#xA9; &#x10FFFF;"), "\u{a9} \u{a9} \u{10ffff}");
+		assert_eq!(decode_entities("&unknown; & &;"), "&unknown; & &;");
+		assert_eq!(decode_entities("x & y;"), "x & y;");
+	}
+
+	#[test]
+	fn lone_ampersands() {
+		// each ampersand used to look for a semicolon up to the end of the text
+		let text = "&".repeat(1 << 20) + ";";
+		assert_eq!(decode_entities(&text), text);
+	}
+}